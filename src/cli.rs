@@ -9,6 +9,9 @@ pub struct Args {
     pub host: String,
     /// Port number to run the application on.
     pub port: u16,
+    /// Validate the Genius API key before serving any requests.
+    #[arg(long)]
+    pub validate_credentials: bool,
 }
 
 impl Args {
@@ -36,6 +39,7 @@ mod tests {
         let args = Args {
             host: host.clone(),
             port,
+            validate_credentials: false,
         };
         assert_eq!(args.address(), format!("{}:{}", host, port))
     }
@@ -0,0 +1,124 @@
+//! Coalescing of identical concurrent computations.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tokio::sync::OnceCell;
+
+/// Shares one computation between concurrent callers with the same key.
+/// Once the computation finishes, the next caller starts a new one.
+#[derive(Debug)]
+pub struct SingleFlight<K, V> {
+    /// Computations currently in flight.
+    flights: Mutex<HashMap<K, Arc<OnceCell<V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    /// Create a new single-flight group.
+    ///
+    /// # Returns
+    ///
+    /// The single-flight group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a computation, or wait for the identical one already in flight.
+    /// If the computation in flight fails, a waiting caller runs its own.
+    ///
+    /// # Args
+    ///
+    /// * `key` - Identifies identical computations.
+    /// * `compute` - Starts the computation.
+    ///
+    /// # Returns
+    ///
+    /// The shared result of the computation.
+    pub async fn run<E, F, Fut>(&self, key: K, compute: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let cell = self
+            .flights
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(OnceCell::new()))
+            .clone();
+        let result = cell.get_or_try_init(compute).await.cloned();
+        let mut flights = self.flights.lock().unwrap_or_else(PoisonError::into_inner);
+        if flights
+            .get(&key)
+            .map(|flight| Arc::ptr_eq(flight, &cell))
+            .unwrap_or(false)
+        {
+            flights.remove(&key);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use rstest::*;
+
+    use super::*;
+
+    async fn slow_source(calls: &AtomicUsize, value: u32) -> Result<u32, String> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        Ok(value)
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_single_flight_concurrent() {
+        let flights = SingleFlight::new();
+        let calls = AtomicUsize::new(0);
+        let results = tokio::join!(
+            flights.run(1, || slow_source(&calls, 10)),
+            flights.run(1, || slow_source(&calls, 10)),
+            flights.run(1, || slow_source(&calls, 10)),
+            flights.run(2, || slow_source(&calls, 20)),
+        );
+        assert_eq!(results, (Ok(10), Ok(10), Ok(10), Ok(20)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_single_flight_sequential() {
+        let flights = SingleFlight::new();
+        let calls = AtomicUsize::new(0);
+        assert_eq!(flights.run(1, || slow_source(&calls, 10)).await, Ok(10));
+        assert_eq!(flights.run(1, || slow_source(&calls, 11)).await, Ok(11));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_single_flight_error() {
+        let flights = SingleFlight::<u32, u32>::new();
+        let result = flights.run(1, || async { Err("oh no!") }).await;
+        assert_eq!(result, Err("oh no!"));
+        assert_eq!(flights.run(1, || async { Ok::<_, ()>(1) }).await, Ok(1));
+    }
+}
@@ -0,0 +1,182 @@
+//! Persistent on-disk cache tier.
+
+use std::{
+    fs,
+    io::{Error as IoError, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{from_slice, to_vec};
+use tokio::task::spawn_blocking;
+
+use crate::StateError;
+
+/// A file-backed cache that outlives Redis.
+/// Each key is stored in its own file. Entries never expire.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    /// Directory holding the cache files.
+    root: PathBuf,
+}
+
+impl DiskCache {
+    /// Create a new on-disk cache, creating its directory if needed.
+    ///
+    /// # Args
+    ///
+    /// * `root` - Directory holding the cache files.
+    ///
+    /// # Returns
+    ///
+    /// The on-disk cache.
+    pub fn new(root: impl AsRef<Path>) -> Result<Self, StateError> {
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root: root.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Return the file path for a cache key.
+    /// Keys are hex encoded so any key is a valid file name.
+    ///
+    /// # Args
+    ///
+    /// * `key` - The cache key.
+    ///
+    /// # Returns
+    ///
+    /// The file path.
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(
+            key.bytes()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+        )
+    }
+
+    /// Read a value from the on-disk cache.
+    /// The file is read on the blocking thread pool.
+    ///
+    /// # Args
+    ///
+    /// * `key` - The cache key.
+    ///
+    /// # Returns
+    ///
+    /// The cached value, if any.
+    pub async fn read<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StateError> {
+        let path = self.path(key);
+        let data = blocking(move || match fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        })
+        .await?;
+        Ok(data.map(|data| from_slice(&data)).transpose()?)
+    }
+
+    /// Write a value to the on-disk cache.
+    /// The file is written on the blocking thread pool.
+    ///
+    /// # Args
+    ///
+    /// * `key` - The cache key.
+    /// * `value` - The value to cache.
+    pub async fn write<T: Serialize>(&self, key: &str, value: &T) -> Result<(), StateError> {
+        let path = self.path(key);
+        let data = to_vec(value)?;
+        blocking(move || fs::write(path, data)).await?;
+        Ok(())
+    }
+
+    /// Remove a value from the on-disk cache.
+    /// The file is removed on the blocking thread pool.
+    ///
+    /// # Args
+    ///
+    /// * `key` - The cache key.
+    ///
+    /// # Returns
+    ///
+    /// Whether there was a value to remove.
+    pub async fn remove(&self, key: &str) -> Result<bool, StateError> {
+        let path = self.path(key);
+        let removed = blocking(move || match fs::remove_file(path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        })
+        .await?;
+        Ok(removed)
+    }
+}
+
+/// Run a filesystem operation on the blocking thread pool.
+///
+/// # Args
+///
+/// * `operation` - The filesystem operation.
+///
+/// # Returns
+///
+/// The result of the operation, or an IO error if the blocking task failed.
+async fn blocking<T: Send + 'static>(
+    operation: impl FnOnce() -> Result<T, IoError> + Send + 'static,
+) -> Result<T, IoError> {
+    spawn_blocking(operation)
+        .await
+        .unwrap_or_else(|e| Err(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::SongData;
+
+    #[fixture]
+    fn root() -> TempDir {
+        TempDir::new().unwrap()
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_disk_cache_round_trip(root: TempDir) {
+        let disk_cache = DiskCache::new(root.path()).unwrap();
+        let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+        disk_cache.write("song/1", &song).await.unwrap();
+        assert_eq!(
+            disk_cache.read::<SongData>("song/1").await.unwrap(),
+            Some(song)
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_disk_cache_missing(root: TempDir) {
+        let disk_cache = DiskCache::new(root.path()).unwrap();
+        assert_eq!(disk_cache.read::<SongData>("song/404").await.unwrap(), None);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_disk_cache_remove(root: TempDir) {
+        let disk_cache = DiskCache::new(root.path()).unwrap();
+        let song = SongData::new(2, "Foobar".into(), "The Sillys".into());
+        disk_cache.write("song/2", &song).await.unwrap();
+        assert!(disk_cache.remove("song/2").await.unwrap());
+        assert_eq!(disk_cache.read::<SongData>("song/2").await.unwrap(), None);
+        assert!(!disk_cache.remove("song/2").await.unwrap());
+    }
+
+    #[rstest]
+    #[case("song/1", "736f6e672f31")]
+    #[case("search/../x", "7365617263682f2e2e2f78")]
+    fn test_disk_cache_path(root: TempDir, #[case] key: &str, #[case] expected: &str) {
+        let disk_cache = DiskCache::new(root.path()).unwrap();
+        assert_eq!(disk_cache.path(key), root.path().join(expected));
+    }
+}
@@ -0,0 +1,278 @@
+//! Standalone documents for sharing graphs outside the API.
+
+use petgraph::graph::DiGraph;
+
+use crate::{snapshot, EdgeData, GraphNode};
+
+/// Namespace of the predicates used in Turtle exports.
+pub const TURTLE_NAMESPACE: &str = "urn:sample-graph:";
+
+/// Base IRI of songs in Turtle exports, followed by the Genius ID.
+const SONG_IRI: &str = "https://genius.com/songs/";
+
+/// Placeholder in the HTML template replaced by the graph JSON.
+const GRAPH_PLACEHOLDER: &str = "__GRAPH_JSON__";
+
+/// Page rendering an embedded graph with vis-network.
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>SampleGraph</title>
+<script src="https://unpkg.com/vis-network@9.1.9/standalone/umd/vis-network.min.js"></script>
+<style>
+html, body, #graph { height: 100%; margin: 0; }
+</style>
+</head>
+<body>
+<div id="graph"></div>
+<script id="graph-data" type="application/json">__GRAPH_JSON__</script>
+<script>
+const graph = JSON.parse(document.getElementById("graph-data").textContent);
+const nodes = graph.nodes.map((node) => ({
+  id: node.song.id,
+  label: node.song.title + "\n" + node.song.artist_name,
+  level: node.degree,
+}));
+const edges = graph.edges.map((edge) => ({
+  from: edge.source,
+  to: edge.target,
+  label: edge.relationship_type,
+  arrows: "to",
+}));
+new vis.Network(
+  document.getElementById("graph"),
+  { nodes: new vis.DataSet(nodes), edges: new vis.DataSet(edges) },
+  { edges: { font: { align: "middle" } } },
+);
+</script>
+</body>
+</html>
+"#;
+
+/// Render a graph as a self-contained HTML page.
+/// The graph data is embedded inline and drawn with vis-network from a CDN.
+///
+/// # Args
+///
+/// * `graph` - The graph to render.
+///
+/// # Returns
+///
+/// The HTML document.
+pub fn html_document(graph: &DiGraph<GraphNode, EdgeData>) -> String {
+    // Escaping `<` stops song titles from closing the data script early.
+    let data = serde_json::to_string(&snapshot(graph))
+        .unwrap_or_else(|_| "{\"nodes\":[],\"edges\":[]}".to_string())
+        .replace('<', "\\u003c");
+    HTML_TEMPLATE.replace(GRAPH_PLACEHOLDER, &data)
+}
+
+/// Escape a string for use as a quoted DOT identifier.
+///
+/// # Args
+///
+/// * `value` - The string to escape.
+///
+/// # Returns
+///
+/// The quoted identifier.
+fn dot_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => {}
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Render a graph in the Graphviz DOT language.
+/// Nodes are identified by Genius ID and labelled `title — artist_name`,
+/// and edges are labelled with the relationship type as named in the API.
+///
+/// # Args
+///
+/// * `graph` - The graph to render.
+///
+/// # Returns
+///
+/// The DOT document.
+pub fn dot_document(graph: &DiGraph<GraphNode, EdgeData>) -> String {
+    let mut document = String::from("digraph samples {\n");
+    for node in graph.node_weights() {
+        document.push_str(&format!(
+            "    {} [label={}];\n",
+            node.song.id,
+            dot_string(&format!("{} — {}", node.song.title, node.song.artist_name)),
+        ));
+    }
+    for edge in graph.edge_indices() {
+        if let Some((source, target)) = graph.edge_endpoints(edge) {
+            document.push_str(&format!(
+                "    {} -> {} [label={}];\n",
+                graph[source].song.id,
+                graph[target].song.id,
+                dot_string(graph[edge].relationship_type.as_str()),
+            ));
+        }
+    }
+    document.push_str("}\n");
+    document
+}
+
+/// Escape a string for use as a Turtle literal.
+///
+/// # Args
+///
+/// * `value` - The string to escape.
+///
+/// # Returns
+///
+/// The quoted literal.
+fn turtle_literal(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Render a graph as RDF triples in Turtle.
+/// Songs are identified by their Genius URL, and each relationship type is a predicate
+/// in the [`TURTLE_NAMESPACE`], named as in the API (e.g. `sg:sampled_in`).
+///
+/// # Args
+///
+/// * `graph` - The graph to render.
+///
+/// # Returns
+///
+/// The Turtle document.
+pub fn turtle_document(graph: &DiGraph<GraphNode, EdgeData>) -> String {
+    let mut document = format!(
+        "@prefix sg: <{}> .\n@prefix song: <{}> .\n",
+        TURTLE_NAMESPACE, SONG_IRI
+    );
+    for node in graph.node_weights() {
+        document.push_str(&format!(
+            "\nsong:{} sg:title {} ;\n    sg:artist_name {} .\n",
+            node.song.id,
+            turtle_literal(&node.song.title),
+            turtle_literal(&node.song.artist_name),
+        ));
+    }
+    if graph.edge_count() > 0 {
+        document.push('\n');
+    }
+    for edge in graph.edge_indices() {
+        if let Some((source, target)) = graph.edge_endpoints(edge) {
+            document.push_str(&format!(
+                "song:{} sg:{} song:{} .\n",
+                graph[source].song.id,
+                graph[edge].relationship_type.as_str(),
+                graph[target].song.id,
+            ));
+        }
+    }
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::{RelationshipType, SongData};
+
+    #[rstest]
+    fn test_turtle_document() {
+        let mut graph = DiGraph::new();
+        let song_1 = graph.add_node(GraphNode::new(
+            0,
+            SongData::new(1, "Foobar".into(), "The Sillys".into()),
+        ));
+        let song_2 = graph.add_node(GraphNode::new(
+            1,
+            SongData::new(2, "Say \"Hi\"\\".into(), "The Seriouses".into()),
+        ));
+        let song_3 = graph.add_node(GraphNode::new(
+            1,
+            SongData::new(3, "Barfoo".into(), "The Sillys".into()),
+        ));
+        graph.add_edge(song_1, song_2, EdgeData::new(RelationshipType::Samples));
+        graph.add_edge(
+            song_1,
+            song_3,
+            EdgeData::new(RelationshipType::InterpolatedBy),
+        );
+        let result = turtle_document(&graph);
+        assert!(result.starts_with("@prefix sg: <urn:sample-graph:> .\n"));
+        assert!(result.contains("@prefix song: <https://genius.com/songs/> .\n"));
+        assert!(
+            result.contains("song:1 sg:title \"Foobar\" ;\n    sg:artist_name \"The Sillys\" .\n")
+        );
+        assert!(result.contains("song:2 sg:title \"Say \\\"Hi\\\"\\\\\" ;"));
+        assert!(result.contains("song:1 sg:samples song:2 .\n"));
+        assert!(result.contains("song:1 sg:interpolated_by song:3 .\n"));
+        assert_eq!(result.matches(" sg:title ").count(), 3);
+    }
+
+    #[rstest]
+    fn test_dot_document() {
+        let mut graph = DiGraph::new();
+        let song_1 = graph.add_node(GraphNode::new(
+            0,
+            SongData::new(1, "Foobar".into(), "The Sillys".into()),
+        ));
+        let song_2 = graph.add_node(GraphNode::new(
+            1,
+            SongData::new(2, "Say \"Hi\"\\".into(), "The\nSeriouses".into()),
+        ));
+        graph.add_edge(song_1, song_2, EdgeData::new(RelationshipType::SampledIn));
+        assert_eq!(
+            dot_document(&graph),
+            "digraph samples {\n    \
+             1 [label=\"Foobar — The Sillys\"];\n    \
+             2 [label=\"Say \\\"Hi\\\"\\\\ — The\\nSeriouses\"];\n    \
+             1 -> 2 [label=\"sampled_in\"];\n\
+             }\n"
+        );
+    }
+
+    #[rstest]
+    fn test_html_document() {
+        let mut graph = DiGraph::new();
+        let song_1 = graph.add_node(GraphNode::new(
+            0,
+            SongData::new(1, "Foobar".into(), "The Sillys".into()),
+        ));
+        let song_2 = graph.add_node(GraphNode::new(
+            1,
+            SongData::new(2, "</script>".into(), "The Seriouses".into()),
+        ));
+        graph.add_edge(song_1, song_2, EdgeData::new(RelationshipType::Samples));
+        let result = html_document(&graph);
+        assert!(result.starts_with("<!DOCTYPE html>"));
+        assert!(result.contains("\"title\":\"Foobar\""));
+        assert!(result.contains("\\u003c/script>"));
+        assert!(result.contains("\"source\":1,\"target\":2"));
+        assert!(result.contains("new vis.Network("));
+        assert!(result.contains("vis-network.min.js"));
+        assert!(!result.contains(GRAPH_PLACEHOLDER));
+    }
+}
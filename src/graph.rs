@@ -0,0 +1,1571 @@
+//! Analysis of song relationship graphs.
+
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    iter::once,
+};
+
+use petgraph::{
+    algo::{connected_components, has_path_connecting, min_spanning_tree},
+    data::Element,
+    graph::{DiGraph, EdgeIndex, NodeIndex},
+    visit::EdgeRef,
+    Direction,
+};
+
+#[cfg(doc)]
+use crate::RelationshipType;
+use crate::{
+    AdjacencyEdge, AdjacencyList, ArtistCount, DegreeSnapshot, EdgeData, GraphBuild, GraphDelta,
+    GraphEdge, GraphElement, GraphMetrics, GraphNode, GraphSnapshot, GraphStats, Reciprocity,
+    Relationship, SongCommunity, SongData, SongRank,
+};
+
+/// Return the shortest path lengths from a node to every node it is connected to.
+/// Edge direction is ignored.
+///
+/// # Args
+///
+/// * `graph` - The graph to search.
+/// * `start` - The node to measure from.
+///
+/// # Returns
+///
+/// The number of edges to each connected node, including the start node itself.
+pub fn path_lengths<N, E>(graph: &DiGraph<N, E>, start: NodeIndex) -> HashMap<NodeIndex, usize> {
+    let mut lengths = HashMap::from([(start, 0)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(current) = queue.pop_front() {
+        let length = lengths[&current] + 1;
+        for next in graph.neighbors_undirected(current) {
+            if let Entry::Vacant(entry) = lengths.entry(next) {
+                entry.insert(length);
+                queue.push_back(next);
+            }
+        }
+    }
+    lengths
+}
+
+/// Compute aggregate metrics about a graph.
+/// Edge direction is ignored.
+///
+/// # Args
+///
+/// * `graph` - The graph to measure.
+///
+/// # Returns
+///
+/// The graph metrics.
+pub fn metrics<N, E>(graph: &DiGraph<N, E>) -> GraphMetrics {
+    let component_count = connected_components(graph);
+    let mut longest = 0;
+    let mut total = 0;
+    let mut pairs = 0;
+    for node in graph.node_indices() {
+        for (_, length) in path_lengths(graph, node)
+            .into_iter()
+            .filter(|(n, _)| *n != node)
+        {
+            longest = longest.max(length);
+            total += length;
+            pairs += 1;
+        }
+    }
+    GraphMetrics {
+        node_count: graph.node_count(),
+        edge_count: graph.edge_count(),
+        component_count,
+        diameter: (component_count == 1).then_some(longest),
+        average_path_length: (pairs > 0).then(|| total as f64 / pairs as f64),
+    }
+}
+
+/// Compute degree statistics of a graph of songs.
+///
+/// # Args
+///
+/// * `graph` - The graph to measure.
+///
+/// # Returns
+///
+/// The graph statistics.
+pub fn degree_stats(graph: &DiGraph<GraphNode, EdgeData>) -> GraphStats {
+    let max_degree = graph
+        .node_indices()
+        .map(|node| {
+            graph.neighbors_directed(node, Direction::Outgoing).count()
+                + graph.neighbors_directed(node, Direction::Incoming).count()
+        })
+        .max()
+        .unwrap_or(0);
+    let mut nodes_per_degree = Vec::new();
+    for node in graph.node_weights() {
+        let degree = usize::from(node.degree);
+        if nodes_per_degree.len() <= degree {
+            nodes_per_degree.resize(degree + 1, 0);
+        }
+        nodes_per_degree[degree] += 1;
+    }
+    let node_count = graph.node_count();
+    let edge_count = graph.edge_count();
+    GraphStats {
+        node_count,
+        edge_count,
+        max_degree,
+        average_out_degree: (node_count > 0).then(|| edge_count as f64 / node_count as f64),
+        nodes_per_degree,
+    }
+}
+
+/// Most label propagation passes made when detecting communities.
+const MAX_PROPAGATION_PASSES: usize = 100;
+
+/// Detect communities of densely-connected nodes by label propagation.
+/// Edge direction is ignored.
+///
+/// Every node starts in its own community, then repeatedly joins the community most common
+/// among its neighbors until no node changes. Ties keep the current community where possible,
+/// then prefer the highest label so the result is deterministic.
+///
+/// # Args
+///
+/// * `graph` - The graph to partition.
+///
+/// # Returns
+///
+/// The community of each node, numbered from 0 in order of first appearance.
+pub fn communities<N, E>(graph: &DiGraph<N, E>) -> HashMap<NodeIndex, usize> {
+    let mut labels = graph
+        .node_indices()
+        .map(|node| (node, node.index()))
+        .collect::<HashMap<_, _>>();
+    for _ in 0..MAX_PROPAGATION_PASSES {
+        let mut changed = false;
+        for node in graph.node_indices() {
+            let mut counts: HashMap<usize, usize> = HashMap::new();
+            for next in graph.neighbors_undirected(node) {
+                *counts.entry(labels[&next]).or_default() += 1;
+            }
+            let Some(most) = counts.values().max().copied() else {
+                continue;
+            };
+            let current = labels[&node];
+            if counts.get(&current) == Some(&most) {
+                continue;
+            }
+            let best = counts
+                .into_iter()
+                .filter(|(_, count)| *count == most)
+                .map(|(label, _)| label)
+                .max()
+                .unwrap_or(current);
+            labels.insert(node, best);
+            changed = true;
+        }
+        if !changed {
+            break;
+        }
+    }
+    let mut numbering = HashMap::new();
+    graph
+        .node_indices()
+        .map(|node| {
+            let next = numbering.len();
+            (node, *numbering.entry(labels[&node]).or_insert(next))
+        })
+        .collect()
+}
+
+/// Assign each song in a graph to a community.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The community of each song, in node order.
+pub fn song_communities(graph: &DiGraph<GraphNode, EdgeData>) -> Vec<SongCommunity> {
+    let communities = communities(graph);
+    graph
+        .node_indices()
+        .map(|node| SongCommunity {
+            song_id: graph[node].song.id,
+            community_id: communities[&node],
+        })
+        .collect()
+}
+
+/// Reduce a graph to the community containing its center (see [`communities`]),
+/// leaving out community members only connected to the center through other communities.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+/// * `center` - The node the graph was built from.
+///
+/// # Returns
+///
+/// The subgraph of the center's community.
+pub fn center_community(
+    graph: &DiGraph<GraphNode, EdgeData>,
+    center: NodeIndex,
+) -> DiGraph<GraphNode, EdgeData> {
+    let communities = communities(graph);
+    let Some(&community) = communities.get(&center) else {
+        return graph.clone();
+    };
+    let members = graph.filter_map(
+        |node, _| (communities[&node] == community).then_some(node),
+        |_, weight| Some(*weight),
+    );
+    let Some(member_center) = members
+        .node_indices()
+        .find(|&member| members[member] == center)
+    else {
+        return graph.clone();
+    };
+    let connected = path_lengths(&members, member_center)
+        .into_keys()
+        .map(|member| members[member])
+        .collect::<HashSet<_>>();
+    graph.filter_map(
+        |node, weight| connected.contains(&node).then(|| weight.clone()),
+        |_, weight| Some(*weight),
+    )
+}
+
+/// Find the songs bridging two artists' catalogs within a graph.
+/// A bridge is a song by neither artist on a shortest path between a song by one and a song by
+/// the other. Artist names are compared case-insensitively and edge direction is ignored.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+/// * `from` - The name of the first artist.
+/// * `to` - The name of the second artist.
+///
+/// # Returns
+///
+/// The bridging songs, in node order.
+pub fn artist_bridges(graph: &DiGraph<GraphNode, EdgeData>, from: &str, to: &str) -> Vec<SongData> {
+    let songs_by = |artist: &str| {
+        let artist = artist.to_lowercase();
+        graph
+            .node_indices()
+            .filter(|node| graph[*node].song.artist_name.to_lowercase() == artist)
+            .collect::<HashSet<_>>()
+    };
+    let nearest = |sources: &HashSet<NodeIndex>| {
+        let mut nearest = HashMap::new();
+        for source in sources {
+            for (node, length) in path_lengths(graph, *source) {
+                let known = nearest.entry(node).or_insert(length);
+                *known = length.min(*known);
+            }
+        }
+        nearest
+    };
+    let from_songs = songs_by(from);
+    let to_songs = songs_by(to);
+    let from_lengths = nearest(&from_songs);
+    let to_lengths = nearest(&to_songs);
+    let Some(shortest) = to_songs
+        .iter()
+        .filter_map(|node| from_lengths.get(node))
+        .min()
+    else {
+        return Vec::new();
+    };
+    graph
+        .node_indices()
+        .filter(|node| !from_songs.contains(node) && !to_songs.contains(node))
+        .filter(
+            |node| match (from_lengths.get(node), to_lengths.get(node)) {
+                (Some(from_length), Some(to_length)) => from_length + to_length == *shortest,
+                _ => false,
+            },
+        )
+        .map(|node| graph[node].song.clone())
+        .collect()
+}
+
+/// Count the songs by each artist in a graph.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// Each artist with their number of songs, most songs first, then by name.
+pub fn artist_counts(graph: &DiGraph<GraphNode, EdgeData>) -> Vec<ArtistCount> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for node in graph.node_weights() {
+        *counts.entry(&node.song.artist_name).or_default() += 1;
+    }
+    let mut counts = counts
+        .into_iter()
+        .map(|(artist, song_count)| ArtistCount {
+            artist: artist.to_string(),
+            song_count,
+        })
+        .collect::<Vec<_>>();
+    counts.sort_by(|a, b| {
+        b.song_count
+            .cmp(&a.song_count)
+            .then_with(|| a.artist.cmp(&b.artist))
+    });
+    counts
+}
+
+/// Score how central each node is with PageRank.
+/// Edge direction is ignored, since relationships point away from the center
+/// only because of the order songs were discovered in.
+///
+/// # Args
+///
+/// * `graph` - The graph to rank.
+/// * `damping` - The chance of following an edge rather than jumping to a random node.
+/// * `iterations` - How many times to redistribute the scores.
+///
+/// # Returns
+///
+/// The score of each node. Scores sum to 1 for a non-empty graph.
+pub fn pagerank<N, E>(
+    graph: &DiGraph<N, E>,
+    damping: f64,
+    iterations: usize,
+) -> HashMap<NodeIndex, f64> {
+    let count = graph.node_count() as f64;
+    let mut ranks = graph
+        .node_indices()
+        .map(|node| (node, 1.0 / count))
+        .collect::<HashMap<_, _>>();
+    let degrees = graph
+        .node_indices()
+        .map(|node| (node, graph.neighbors_undirected(node).count()))
+        .collect::<HashMap<_, _>>();
+    for _ in 0..iterations {
+        let dangling = graph
+            .node_indices()
+            .filter(|node| degrees[node] == 0)
+            .map(|node| ranks[&node])
+            .sum::<f64>();
+        let base = (1.0 - damping + damping * dangling) / count;
+        ranks = graph
+            .node_indices()
+            .map(|node| {
+                let incoming = graph
+                    .neighbors_undirected(node)
+                    .map(|next| ranks[&next] / degrees[&next] as f64)
+                    .sum::<f64>();
+                (node, base + damping * incoming)
+            })
+            .collect();
+    }
+    ranks
+}
+
+/// Score how central each song in a graph is with PageRank.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+/// * `damping` - The chance of following an edge rather than jumping to a random song.
+/// * `iterations` - How many times to redistribute the scores.
+///
+/// # Returns
+///
+/// The score of each song, in node order.
+pub fn song_ranks(
+    graph: &DiGraph<GraphNode, EdgeData>,
+    damping: f64,
+    iterations: usize,
+) -> Vec<SongRank> {
+    let ranks = pagerank(graph, damping, iterations);
+    graph
+        .node_indices()
+        .map(|node| SongRank {
+            song_id: graph[node].song.id,
+            score: ranks[&node],
+        })
+        .collect()
+}
+
+/// Count how many pairs of related songs have a relationship reported by Genius from both songs.
+/// Each pair is counted once, however many relationships either song reports.
+/// Relationships to songs without known relationships are ignored.
+///
+/// # Args
+///
+/// * `relationships` - The relationships of each song, keyed by Genius ID.
+///
+/// # Returns
+///
+/// The reciprocity statistics.
+pub fn reciprocity(relationships: &HashMap<u32, Vec<Relationship>>) -> Reciprocity {
+    let mut pairs: HashMap<(u32, u32), bool> = HashMap::new();
+    for (&id, song_relationships) in relationships {
+        for relationship in song_relationships {
+            let other_id = relationship.song.id;
+            if let Some(other) = relationships.get(&other_id) {
+                let returned = other.iter().any(|r| {
+                    r.song.id == id
+                        && r.relationship_type == relationship.relationship_type.inverse()
+                });
+                *pairs
+                    .entry((id.min(other_id), id.max(other_id)))
+                    .or_default() |= returned;
+            }
+        }
+    }
+    let reciprocated = pairs.values().filter(|&&returned| returned).count();
+    let one_way = pairs.len() - reciprocated;
+    let total = pairs.len();
+    Reciprocity {
+        reciprocated,
+        one_way,
+        ratio: (total > 0).then(|| reciprocated as f64 / total as f64),
+    }
+}
+
+/// Find the shortest directed cycle of relationships through a song.
+/// Only songs with known relationships are followed.
+///
+/// # Args
+///
+/// * `relationships` - The relationships of each song, keyed by Genius ID.
+/// * `center` - The Genius ID of the song the cycle must pass through.
+///
+/// # Returns
+///
+/// The Genius IDs along the cycle starting from the center, if there is a cycle.
+/// The last song relates back to the center.
+pub fn shortest_cycle(
+    relationships: &HashMap<u32, Vec<Relationship>>,
+    center: u32,
+) -> Option<Vec<u32>> {
+    let mut parents = HashMap::new();
+    let mut queue = VecDeque::from([center]);
+    while let Some(current) = queue.pop_front() {
+        for relationship in relationships.get(&current).into_iter().flatten() {
+            let next = relationship.song.id;
+            if next == center {
+                let mut chain = vec![current];
+                let mut node = current;
+                while node != center {
+                    node = parents[&node];
+                    chain.push(node);
+                }
+                chain.reverse();
+                return Some(chain);
+            }
+            if let Entry::Vacant(entry) = parents.entry(next) {
+                entry.insert(current);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// Re-root a graph at its earliest released song, the original the rest descend from.
+/// The root becomes the first node, and degrees are recomputed as the distance from it,
+/// ignoring edge direction. Other nodes and every edge keep their order.
+/// Songs without a release date are never chosen; ties go to the song closest to the old center.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The re-rooted graph, unchanged if no song has a release date.
+pub fn family_tree(graph: DiGraph<GraphNode, EdgeData>) -> DiGraph<GraphNode, EdgeData> {
+    let root = graph
+        .node_indices()
+        .filter(|&index| graph[index].song.release_date.is_some())
+        .min_by(|&a, &b| {
+            graph[a]
+                .song
+                .release_date
+                .cmp(&graph[b].song.release_date)
+                .then(graph[a].degree.cmp(&graph[b].degree))
+        });
+    let Some(root) = root else {
+        return graph;
+    };
+    let lengths = path_lengths(&graph, root);
+    let mut tree = DiGraph::with_capacity(graph.node_count(), graph.edge_count());
+    let mut indices = HashMap::new();
+    for index in once(root).chain(graph.node_indices().filter(|&index| index != root)) {
+        let mut node = graph[index].clone();
+        if let Some(&length) = lengths.get(&index) {
+            node.degree = u8::try_from(length).unwrap_or(u8::MAX);
+        }
+        indices.insert(index, tree.add_node(node));
+    }
+    for edge in graph.edge_references() {
+        tree.add_edge(
+            indices[&edge.source()],
+            indices[&edge.target()],
+            *edge.weight(),
+        );
+    }
+    tree
+}
+
+/// Reduce a graph to a maximum spanning tree of each connected component by
+/// [`RelationshipType::weight`], ignoring edge direction, so the strongest ties are kept.
+/// Every node is kept, and kept edges keep their direction.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The spanning forest of the graph.
+pub fn spanning_tree(graph: &DiGraph<GraphNode, EdgeData>) -> DiGraph<GraphNode, EdgeData> {
+    // Reversing the weights makes the minimum spanning tree the maximum one, and pairing
+    // each cost with its edge index identifies which of any parallel edges was kept.
+    let costs = graph.map(
+        |_, _| (),
+        |edge, weight| (Reverse(weight.relationship_type.weight()), edge.index()),
+    );
+    let kept = min_spanning_tree(&costs)
+        .filter_map(|element| match element {
+            Element::Edge { weight, .. } => Some(EdgeIndex::new(weight.1)),
+            Element::Node { .. } => None,
+        })
+        .collect::<HashSet<_>>();
+    graph.filter_map(
+        |_, node| Some(node.clone()),
+        |edge, weight| kept.contains(&edge).then_some(*weight),
+    )
+}
+
+/// Break every cycle in a graph by dropping as few strong edges as it can.
+/// Edges are kept strongest first (see [`RelationshipType::weight`]), and an edge is dropped
+/// if it would close a cycle with the edges already kept. Among equally strong edges,
+/// those between older songs and those pointing from the older song to the newer are kept first.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The acyclic graph and the edges dropped from it.
+pub fn acyclic(
+    graph: &DiGraph<GraphNode, EdgeData>,
+) -> (DiGraph<GraphNode, EdgeData>, Vec<GraphEdge>) {
+    let mut edges = graph.edge_indices().collect::<Vec<_>>();
+    edges.sort_by_key(|&edge| {
+        let (source, target) = graph.edge_endpoints(edge).unwrap_or_default();
+        let source_date = &graph[source].song.release_date;
+        let target_date = &graph[target].song.release_date;
+        let newest = source_date.as_ref().max(target_date.as_ref());
+        (
+            Reverse(graph[edge].relationship_type.weight()),
+            newest.is_none(),
+            newest,
+            source_date > target_date,
+            edge,
+        )
+    });
+    let mut kept = graph.map(|_, _| (), |_, _| ());
+    kept.clear_edges();
+    let mut removed = HashSet::new();
+    for edge in edges {
+        let Some((source, target)) = graph.edge_endpoints(edge) else {
+            continue;
+        };
+        if has_path_connecting(&kept, target, source, None) {
+            removed.insert(edge);
+        } else {
+            kept.add_edge(source, target, ());
+        }
+    }
+    let removed_edges = graph
+        .edge_indices()
+        .filter(|edge| removed.contains(edge))
+        .filter_map(|edge| {
+            let (source, target) = graph.edge_endpoints(edge)?;
+            Some(GraphEdge {
+                source: graph[source].song.id,
+                target: graph[target].song.id,
+                relationship_type: graph[edge].relationship_type,
+                discovery: None,
+            })
+        })
+        .collect();
+    let dag = graph.filter_map(
+        |_, node| Some(node.clone()),
+        |edge, weight| (!removed.contains(&edge)).then_some(*weight),
+    );
+    (dag, removed_edges)
+}
+
+/// Drop edges weaker than a threshold from a built graph,
+/// along with any songs no longer connected to a center through the remaining edges.
+///
+/// # Args
+///
+/// * `build` - A built graph of song relationships.
+/// * `centers` - The nodes the graph was built from.
+/// * `min_weight` - The lowest [`RelationshipType::weight`] an edge may have.
+///
+/// # Returns
+///
+/// The pruned graph, with its edge sources kept in edge order.
+pub fn prune_weak_edges(build: GraphBuild, centers: &[NodeIndex], min_weight: u32) -> GraphBuild {
+    let graph = &build.graph;
+    let strong = |edge: EdgeIndex| graph[edge].relationship_type.weight() >= min_weight;
+    let mut connected = centers.iter().copied().collect::<HashSet<_>>();
+    let mut queue = centers.iter().copied().collect::<VecDeque<_>>();
+    while let Some(current) = queue.pop_front() {
+        let edges = graph
+            .edges_directed(current, Direction::Outgoing)
+            .chain(graph.edges_directed(current, Direction::Incoming));
+        for edge in edges.filter(|edge| strong(edge.id())) {
+            let next = if edge.source() == current {
+                edge.target()
+            } else {
+                edge.source()
+            };
+            if connected.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    let kept = |edge: EdgeIndex| {
+        strong(edge)
+            && graph
+                .edge_endpoints(edge)
+                .map(|(source, _)| connected.contains(&source))
+                .unwrap_or(false)
+    };
+    let edge_sources = graph
+        .edge_indices()
+        .zip(build.edge_sources.iter())
+        .filter(|(edge, _)| kept(*edge))
+        .map(|(_, source)| *source)
+        .collect();
+    GraphBuild {
+        graph: graph.filter_map(
+            |node, weight| connected.contains(&node).then(|| weight.clone()),
+            |edge, weight| kept(edge).then_some(*weight),
+        ),
+        edge_sources,
+        ..build
+    }
+}
+
+/// Store the nodes and edges of a graph by Genius ID.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The snapshot of the graph.
+pub fn snapshot(graph: &DiGraph<GraphNode, EdgeData>) -> GraphSnapshot {
+    GraphSnapshot {
+        nodes: graph.node_weights().cloned().collect(),
+        edges: graph
+            .edge_indices()
+            .filter_map(|edge| {
+                let (source, target) = graph.edge_endpoints(edge)?;
+                Some(GraphEdge {
+                    source: graph[source].song.id,
+                    target: graph[target].song.id,
+                    relationship_type: graph[edge].relationship_type,
+                    discovery: None,
+                })
+            })
+            .collect(),
+    }
+}
+
+/// List the songs and relationships of a graph by Genius ID.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The adjacency list of the graph.
+pub fn to_adjacency(graph: &DiGraph<GraphNode, EdgeData>) -> AdjacencyList {
+    AdjacencyList {
+        nodes: graph.node_weights().map(|node| node.song.clone()).collect(),
+        edges: graph
+            .edge_references()
+            .map(|edge| AdjacencyEdge {
+                source: graph[edge.source()].song.id,
+                target: graph[edge.target()].song.id,
+                relationship_type: edge.weight().relationship_type,
+            })
+            .collect(),
+    }
+}
+
+/// Store the nodes and edges of a graph by Genius ID, recording how each edge was discovered.
+///
+/// # Args
+///
+/// * `build` - A built graph of song relationships.
+///
+/// # Returns
+///
+/// The snapshot of the graph with annotated edges.
+pub fn annotated_snapshot(build: &GraphBuild) -> GraphSnapshot {
+    let mut snapshot = snapshot(&build.graph);
+    for (edge, source) in snapshot.edges.iter_mut().zip(&build.edge_sources) {
+        edge.discovery = Some(*source);
+    }
+    snapshot
+}
+
+/// Offset basis for the 64-bit FNV-1a hash.
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// Prime for the 64-bit FNV-1a hash.
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Compute a version for a graph that only changes when its data changes.
+/// Nodes and edges are sorted by Genius ID first, so the order songs were discovered in
+/// does not matter. FNV-1a is used rather than the standard library hasher so versions
+/// stay the same across releases.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The graph version as a hex string.
+pub fn graph_version(graph: &DiGraph<GraphNode, EdgeData>) -> String {
+    let mut snapshot = snapshot(graph);
+    snapshot.nodes.sort_by_key(|node| node.song.id);
+    snapshot
+        .edges
+        .sort_by_key(|edge| (edge.source, edge.target, edge.relationship_type.as_str()));
+    format!(
+        "{:016x}",
+        fnv1a(&serde_json::to_vec(&snapshot).unwrap_or_default())
+    )
+}
+
+/// Hash bytes with 64-bit FNV-1a.
+///
+/// # Args
+///
+/// * `bytes` - The bytes to hash.
+///
+/// # Returns
+///
+/// The hash.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Compute a weak entity tag for a response built from a graph.
+/// Responses differ by query parameters as well as graph data, e.g. by format,
+/// so the parameters are hashed in alongside the graph version, sorted by name
+/// so the order they were given in does not matter.
+///
+/// # Args
+///
+/// * `version` - The version of the graph, from [`graph_version`].
+/// * `params` - The query parameters the response was built with.
+///
+/// # Returns
+///
+/// The entity tag, quoted and marked weak, ready for an `ETag` header.
+pub fn graph_etag(version: &str, params: &HashMap<String, String>) -> String {
+    let mut params = params.iter().collect::<Vec<_>>();
+    params.sort();
+    let variant = fnv1a(&serde_json::to_vec(&params).unwrap_or_default());
+    format!("W/\"{}-{:016x}\"", version, variant)
+}
+
+/// Compute the nodes and edges added and removed between two versions of a graph.
+/// Nodes are matched by Genius ID.
+///
+/// # Args
+///
+/// * `previous` - The older version of the graph.
+/// * `current` - The newer version of the graph.
+///
+/// # Returns
+///
+/// The changes from the older version to the newer version.
+pub fn diff(previous: &GraphSnapshot, current: &GraphSnapshot) -> GraphDelta {
+    let only_in = |a: &GraphSnapshot, b: &GraphSnapshot| {
+        let ids = b.nodes.iter().map(|n| n.song.id).collect::<HashSet<_>>();
+        let edges = b.edges.iter().collect::<HashSet<_>>();
+        a.nodes
+            .iter()
+            .filter(|n| !ids.contains(&n.song.id))
+            .map(|n| GraphElement::Node(Box::new(n.clone())))
+            .chain(
+                a.edges
+                    .iter()
+                    .filter(|e| !edges.contains(e))
+                    .copied()
+                    .map(GraphElement::Edge),
+            )
+            .collect()
+    };
+    GraphDelta {
+        added: only_in(current, previous),
+        removed: only_in(previous, current),
+    }
+}
+
+/// Return a graph as it would appear at each degree up to its maximum.
+/// Every song is tagged with its degree during the one search that built the graph,
+/// so each snapshot is a subgraph of the full graph rather than a rebuild.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+/// * `degree` - The maximum degree the graph was built to.
+///
+/// # Returns
+///
+/// The snapshots from degree 1 up to the maximum degree.
+pub fn degree_snapshots(graph: &DiGraph<GraphNode, EdgeData>, degree: u8) -> Vec<DegreeSnapshot> {
+    (1..=degree)
+        .map(|d| DegreeSnapshot {
+            degree: d,
+            graph: graph.filter_map(
+                |_, node| (node.degree <= d).then(|| node.clone()),
+                |_, edge| Some(*edge),
+            ),
+        })
+        .collect()
+}
+
+/// Header row of the CSV export, one row per edge.
+pub const CSV_HEADER: &str =
+    "source_id,source_title,source_artist,target_id,target_title,target_artist,relationship_type\n";
+
+/// Escape a value for a CSV field.
+/// Values containing commas, quotes, or line breaks are quoted.
+///
+/// # Args
+///
+/// * `value` - The raw value.
+///
+/// # Returns
+///
+/// The escaped value.
+pub fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Convert a graph into CSV rows lazily, one row per edge.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The header row followed by a row for each edge, each ending in a newline.
+pub fn csv_rows(graph: DiGraph<GraphNode, EdgeData>) -> impl Iterator<Item = String> {
+    let rows = (0..graph.edge_count()).filter_map(move |i| {
+        let edge = EdgeIndex::new(i);
+        let (source, target) = graph.edge_endpoints(edge)?;
+        let (source, target) = (&graph[source].song, &graph[target].song);
+        Some(format!(
+            "{},{},{},{},{},{},{}\n",
+            source.id,
+            csv_field(&source.title),
+            csv_field(&source.artist_name),
+            target.id,
+            csv_field(&target.title),
+            csv_field(&target.artist_name),
+            graph[edge].relationship_type.as_str(),
+        ))
+    });
+    once(CSV_HEADER.to_string()).chain(rows)
+}
+
+/// Namespace of GraphML documents.
+pub const GRAPHML_NAMESPACE: &str = "http://graphml.graphdrawing.org/xmlns";
+
+/// Escape a value for XML text or a quoted attribute.
+///
+/// # Args
+///
+/// * `value` - The raw value.
+///
+/// # Returns
+///
+/// The escaped value.
+pub fn xml_escape(value: &str) -> Cow<'_, str> {
+    if !value.contains(['<', '>', '&', '"', '\'']) {
+        return Cow::Borrowed(value);
+    }
+    let mut escaped = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Write a GraphML data element holding a node or edge attribute.
+///
+/// # Args
+///
+/// * `document` - The document to write to.
+/// * `indent` - Indentation before the element.
+/// * `key` - The GraphML key of the data element.
+/// * `value` - The text of the element, escaped as it's written.
+fn write_graphml_data(document: &mut String, indent: &str, key: &str, value: &str) {
+    document.push_str(&format!(
+        "{}<data key=\"{}\">{}</data>\n",
+        indent,
+        key,
+        xml_escape(value)
+    ));
+}
+
+/// Convert a graph into a GraphML document, e.g. for importing into Gephi.
+/// Nodes are identified by their Genius ID and carry `title`, `artist`, and `degree` attributes.
+/// Edges carry a `relationship` attribute.
+///
+/// # Args
+///
+/// * `graph` - A graph of song relationships.
+///
+/// # Returns
+///
+/// The GraphML document.
+pub fn to_graphml(graph: &DiGraph<GraphNode, EdgeData>) -> String {
+    let mut document = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<graphml xmlns=\"{}\">\n",
+        GRAPHML_NAMESPACE
+    );
+    for (id, domain, name, kind) in [
+        ("title", "node", "title", "string"),
+        ("artist", "node", "artist", "string"),
+        ("degree", "node", "degree", "int"),
+        ("relationship", "edge", "relationship", "string"),
+    ] {
+        document.push_str(&format!(
+            "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>\n",
+            id, domain, name, kind
+        ));
+    }
+    document.push_str("  <graph id=\"G\" edgedefault=\"directed\">\n");
+    for node in graph.node_weights() {
+        document.push_str(&format!("    <node id=\"n{}\">\n", node.song.id));
+        write_graphml_data(&mut document, "      ", "title", &node.song.title);
+        write_graphml_data(&mut document, "      ", "artist", &node.song.artist_name);
+        write_graphml_data(&mut document, "      ", "degree", &node.degree.to_string());
+        document.push_str("    </node>\n");
+    }
+    for edge in graph.edge_references() {
+        document.push_str(&format!(
+            "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">\n",
+            edge.id().index(),
+            graph[edge.source()].song.id,
+            graph[edge.target()].song.id,
+        ));
+        write_graphml_data(
+            &mut document,
+            "      ",
+            "relationship",
+            edge.weight().relationship_type.as_str(),
+        );
+        document.push_str("    </edge>\n");
+    }
+    document.push_str("  </graph>\n</graphml>\n");
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::algo::is_cyclic_directed;
+    use rstest::*;
+
+    use super::*;
+    use crate::{RelationshipType, SongData};
+
+    #[fixture]
+    fn graph() -> DiGraph<u32, ()> {
+        let mut graph = DiGraph::new();
+        let song_1 = graph.add_node(1);
+        let song_2 = graph.add_node(2);
+        let song_3 = graph.add_node(3);
+        let song_4 = graph.add_node(4);
+        graph.add_edge(song_1, song_2, ());
+        graph.add_edge(song_3, song_2, ());
+        graph.add_edge(song_1, song_4, ());
+        graph
+    }
+
+    #[rstest]
+    fn test_path_lengths(graph: DiGraph<u32, ()>) {
+        let result = path_lengths(&graph, NodeIndex::new(2));
+        assert_eq!(
+            result,
+            HashMap::from([
+                (NodeIndex::new(0), 2),
+                (NodeIndex::new(1), 1),
+                (NodeIndex::new(2), 0),
+                (NodeIndex::new(3), 3),
+            ])
+        );
+    }
+
+    #[rstest]
+    fn test_communities() {
+        let mut graph = DiGraph::<u32, ()>::new();
+        let nodes = (0..6).map(|i| graph.add_node(i)).collect::<Vec<_>>();
+        for (a, b) in [(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3), (2, 3)] {
+            graph.add_edge(nodes[a], nodes[b], ());
+        }
+        let result = communities(&graph);
+        let labels = nodes.iter().map(|n| result[n]).collect::<Vec<_>>();
+        assert_eq!(labels, vec![0, 0, 0, 1, 1, 1]);
+    }
+
+    #[rstest]
+    fn test_communities_isolated(mut graph: DiGraph<u32, ()>) {
+        let isolated = graph.add_node(5);
+        let result = communities(&graph);
+        assert_eq!(result.len(), 5);
+        assert_ne!(result[&isolated], result[&NodeIndex::new(0)]);
+    }
+
+    #[rstest]
+    #[case(&[(1, 2), (2, 3), (3, 1), (2, 1)], Some(vec![1, 2]))]
+    #[case(&[(1, 2), (2, 3), (3, 1)], Some(vec![1, 2, 3]))]
+    #[case(&[(1, 1), (1, 2), (2, 1)], Some(vec![1]))]
+    #[case(&[(1, 2), (2, 3), (3, 2)], None)]
+    fn test_shortest_cycle(#[case] edges: &[(u32, u32)], #[case] expected: Option<Vec<u32>>) {
+        let mut relationships: HashMap<u32, Vec<Relationship>> = HashMap::new();
+        for (from, to) in edges {
+            relationships
+                .entry(*from)
+                .or_default()
+                .push(Relationship::new(
+                    RelationshipType::Samples,
+                    SongData::new(*to, "".into(), "".into()),
+                ));
+        }
+        assert_eq!(shortest_cycle(&relationships, 1), expected);
+    }
+
+    #[rstest]
+    fn test_metrics(graph: DiGraph<u32, ()>) {
+        let result = metrics(&graph);
+        assert_eq!(result.node_count, 4);
+        assert_eq!(result.edge_count, 3);
+        assert_eq!(result.component_count, 1);
+        assert_eq!(result.diameter, Some(3));
+        assert_eq!(result.average_path_length, Some(10.0 / 6.0));
+    }
+
+    #[rstest]
+    fn test_metrics_disconnected(mut graph: DiGraph<u32, ()>) {
+        graph.add_node(5);
+        let result = metrics(&graph);
+        assert_eq!(result.component_count, 2);
+        assert_eq!(result.diameter, None);
+        assert_eq!(result.average_path_length, Some(10.0 / 6.0));
+    }
+
+    #[rstest]
+    fn test_metrics_empty() {
+        let result = metrics(&DiGraph::<u32, ()>::new());
+        assert_eq!(result.node_count, 0);
+        assert_eq!(result.diameter, None);
+        assert_eq!(result.average_path_length, None);
+    }
+
+    #[rstest]
+    fn test_degree_stats(mut song_graph: DiGraph<GraphNode, EdgeData>) {
+        let song_4 = song_graph.add_node(GraphNode::new(
+            2,
+            SongData::new(4, "Foobar".into(), "Barfoo".into()),
+        ));
+        song_graph.add_edge(
+            NodeIndex::new(2),
+            song_4,
+            EdgeData::new(RelationshipType::Samples),
+        );
+        let result = degree_stats(&song_graph);
+        assert_eq!(
+            result,
+            GraphStats {
+                node_count: 4,
+                edge_count: 3,
+                max_degree: 2,
+                average_out_degree: Some(0.75),
+                nodes_per_degree: vec![1, 2, 1],
+            }
+        );
+    }
+
+    #[rstest]
+    fn test_degree_stats_empty() {
+        let result = degree_stats(&DiGraph::new());
+        assert_eq!(result.node_count, 0);
+        assert_eq!(result.max_degree, 0);
+        assert_eq!(result.average_out_degree, None);
+        assert!(result.nodes_per_degree.is_empty());
+    }
+
+    #[rstest]
+    fn test_reciprocity() {
+        let song = |id| SongData::new(id, "Foobar".into(), "Barfoo".into());
+        let relationships = HashMap::from([
+            (
+                1,
+                vec![
+                    Relationship::new(RelationshipType::Samples, song(2)),
+                    Relationship::new(RelationshipType::Samples, song(3)),
+                    Relationship::new(RelationshipType::Samples, song(4)),
+                ],
+            ),
+            (
+                2,
+                vec![Relationship::new(RelationshipType::SampledIn, song(1))],
+            ),
+            (
+                3,
+                vec![Relationship::new(RelationshipType::Interpolates, song(1))],
+            ),
+        ]);
+        let result = reciprocity(&relationships);
+        assert_eq!(result.reciprocated, 1);
+        assert_eq!(result.one_way, 1);
+        assert_eq!(result.ratio, Some(0.5));
+    }
+
+    #[rstest]
+    fn test_reciprocity_empty() {
+        let result = reciprocity(&HashMap::new());
+        assert_eq!(result.reciprocated, 0);
+        assert_eq!(result.one_way, 0);
+        assert_eq!(result.ratio, None);
+    }
+
+    #[fixture]
+    fn song_graph() -> DiGraph<GraphNode, EdgeData> {
+        let song = |id| SongData::new(id, "Foobar".into(), "Barfoo".into());
+        let mut graph = DiGraph::new();
+        let song_1 = graph.add_node(GraphNode::new(0, song(1)));
+        let song_2 = graph.add_node(GraphNode::new(1, song(2)));
+        let song_3 = graph.add_node(GraphNode::new(1, song(3)));
+        graph.add_edge(song_1, song_2, EdgeData::new(RelationshipType::Samples));
+        graph.add_edge(
+            song_1,
+            song_3,
+            EdgeData::new(RelationshipType::Interpolates),
+        );
+        graph
+    }
+
+    #[rstest]
+    fn test_acyclic() {
+        let song = |id, release_date: &str| SongData {
+            release_date: Some(release_date.into()),
+            ..SongData::new(id, "Foobar".into(), "Barfoo".into())
+        };
+        let mut graph = DiGraph::new();
+        let song_1 = graph.add_node(GraphNode::new(0, song(1, "1990-01-01")));
+        let song_2 = graph.add_node(GraphNode::new(1, song(2, "1980-01-01")));
+        let song_3 = graph.add_node(GraphNode::new(2, song(3, "2000-01-01")));
+        graph.add_edge(song_1, song_2, EdgeData::new(RelationshipType::Samples));
+        graph.add_edge(song_2, song_1, EdgeData::new(RelationshipType::SampledIn));
+        graph.add_edge(
+            song_2,
+            song_3,
+            EdgeData::new(RelationshipType::InterpolatedBy),
+        );
+        graph.add_edge(song_3, song_1, EdgeData::new(RelationshipType::RemixOf));
+        graph.add_edge(song_1, song_3, EdgeData::new(RelationshipType::CoveredBy));
+        graph.add_edge(song_3, song_3, EdgeData::new(RelationshipType::Samples));
+        assert!(is_cyclic_directed(&graph));
+
+        let (result, removed) = acyclic(&graph);
+        assert!(!is_cyclic_directed(&result));
+        assert_eq!(result.node_count(), 3);
+        assert_eq!(result.edge_count() + removed.len(), graph.edge_count());
+        let removed = removed
+            .iter()
+            .map(|edge| (edge.source, edge.target, edge.relationship_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            removed,
+            vec![
+                (1, 2, RelationshipType::Samples),
+                (1, 3, RelationshipType::CoveredBy),
+                (3, 3, RelationshipType::Samples),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_spanning_tree(mut song_graph: DiGraph<GraphNode, EdgeData>) {
+        let song = |id| SongData::new(id, "Foobar".into(), "Barfoo".into());
+        let song_4 = song_graph.add_node(GraphNode::new(2, song(4)));
+        let song_5 = song_graph.add_node(GraphNode::new(2, song(5)));
+        let song_6 = song_graph.add_node(GraphNode::new(2, song(6)));
+        song_graph.add_edge(
+            NodeIndex::new(2),
+            NodeIndex::new(1),
+            EdgeData::new(RelationshipType::CoverOf),
+        );
+        song_graph.add_edge(
+            NodeIndex::new(1),
+            NodeIndex::new(2),
+            EdgeData::new(RelationshipType::Samples),
+        );
+        song_graph.add_edge(song_4, song_5, EdgeData::new(RelationshipType::Samples));
+        song_graph.add_edge(song_5, song_6, EdgeData::new(RelationshipType::RemixOf));
+        song_graph.add_edge(
+            song_6,
+            song_4,
+            EdgeData::new(RelationshipType::LiveVersionOf),
+        );
+
+        let result = spanning_tree(&song_graph);
+        assert_eq!(result.node_count(), song_graph.node_count());
+        assert_eq!(
+            result.edge_count(),
+            result.node_count() - connected_components(&song_graph)
+        );
+        assert_eq!(connected_components(&result), 2);
+        let edges = result
+            .edge_indices()
+            .map(|edge| {
+                let (source, target) = result.edge_endpoints(edge).unwrap();
+                (
+                    result[source].song.id,
+                    result[target].song.id,
+                    result[edge].relationship_type,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            vec![
+                (1, 2, RelationshipType::Samples),
+                (2, 3, RelationshipType::Samples),
+                (4, 5, RelationshipType::Samples),
+                (5, 6, RelationshipType::RemixOf),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(0.85, 20)]
+    #[case(0.5, 100)]
+    #[case(0.85, 0)]
+    fn test_song_ranks(
+        song_graph: DiGraph<GraphNode, EdgeData>,
+        #[case] damping: f64,
+        #[case] iterations: usize,
+    ) {
+        let result = song_ranks(&song_graph, damping, iterations);
+        let total = result.iter().map(|r| r.score).sum::<f64>();
+        assert!((total - 1.0).abs() < 1e-9);
+        if iterations > 0 {
+            assert!(result[0].score > result[1].score);
+            assert!((result[1].score - result[2].score).abs() < 1e-9);
+        }
+    }
+
+    #[rstest]
+    fn test_pagerank_dangling(mut graph: DiGraph<u32, ()>) {
+        let isolated = graph.add_node(5);
+        let result = pagerank(&graph, 0.85, 50);
+        let total = result.values().sum::<f64>();
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(result[&NodeIndex::new(0)] > result[&isolated]);
+    }
+
+    #[rstest]
+    fn test_pagerank_empty() {
+        assert!(pagerank(&DiGraph::<u32, ()>::new(), 0.85, 20).is_empty());
+    }
+
+    #[rstest]
+    fn test_snapshot(song_graph: DiGraph<GraphNode, EdgeData>) {
+        let result = snapshot(&song_graph);
+        assert_eq!(
+            result.nodes.iter().map(|n| n.song.id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            result.edges,
+            vec![
+                GraphEdge {
+                    source: 1,
+                    target: 2,
+                    relationship_type: RelationshipType::Samples,
+                    discovery: None,
+                },
+                GraphEdge {
+                    source: 1,
+                    target: 3,
+                    relationship_type: RelationshipType::Interpolates,
+                    discovery: None,
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(&[None, Some("1990-01-01"), Some("1971-03-05")], &[(3, 0), (1, 1), (2, 2)])]
+    #[case(&[Some("1971-03-05"), None, Some("1971-03-05")], &[(1, 0), (2, 1), (3, 1)])]
+    #[case(&[None, None, None], &[(1, 0), (2, 1), (3, 1)])]
+    fn test_family_tree(
+        mut song_graph: DiGraph<GraphNode, EdgeData>,
+        #[case] release_dates: &[Option<&str>],
+        #[case] expected: &[(u32, u8)],
+    ) {
+        for (node, release_date) in song_graph.node_weights_mut().zip(release_dates) {
+            node.song.release_date = release_date.map(String::from);
+        }
+        let result = family_tree(song_graph.clone());
+        let nodes = result
+            .node_weights()
+            .map(|n| (n.song.id, n.degree))
+            .collect::<Vec<_>>();
+        assert_eq!(nodes, expected);
+        let edges = |graph: &DiGraph<GraphNode, EdgeData>| {
+            graph
+                .edge_references()
+                .map(|e| (graph[e.source()].song.id, graph[e.target()].song.id))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(edges(&result), edges(&song_graph));
+    }
+
+    #[rstest]
+    fn test_graph_version(song_graph: DiGraph<GraphNode, EdgeData>) {
+        let song = |id| SongData::new(id, "Foobar".into(), "Barfoo".into());
+        let mut reordered = DiGraph::new();
+        let song_1 = reordered.add_node(GraphNode::new(0, song(1)));
+        let song_3 = reordered.add_node(GraphNode::new(1, song(3)));
+        let song_2 = reordered.add_node(GraphNode::new(1, song(2)));
+        reordered.add_edge(
+            song_1,
+            song_3,
+            EdgeData::new(RelationshipType::Interpolates),
+        );
+        reordered.add_edge(song_1, song_2, EdgeData::new(RelationshipType::Samples));
+
+        let version = graph_version(&song_graph);
+        assert_eq!(version.len(), 16);
+        assert_eq!(graph_version(&song_graph.clone()), version);
+        assert_eq!(graph_version(&reordered), version);
+
+        let mut changed = song_graph.clone();
+        changed[NodeIndex::new(2)].song.title = "Changed".into();
+        assert_ne!(graph_version(&changed), version);
+        let mut changed = song_graph;
+        changed.add_edge(
+            NodeIndex::new(2),
+            NodeIndex::new(1),
+            EdgeData::new(RelationshipType::Samples),
+        );
+        assert_ne!(graph_version(&changed), version);
+    }
+
+    #[rstest]
+    fn test_graph_etag() {
+        let params = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        let etag = graph_etag("0123456789abcdef", &params(&[("degree", "1")]));
+        assert!(etag.starts_with("W/\"0123456789abcdef-"));
+        assert!(etag.ends_with('"'));
+        assert_eq!(
+            graph_etag("0123456789abcdef", &params(&[("degree", "1")])),
+            etag
+        );
+        assert_ne!(
+            graph_etag("fedcba9876543210", &params(&[("degree", "1")])),
+            etag
+        );
+        assert_ne!(
+            graph_etag("0123456789abcdef", &params(&[("degree", "2")])),
+            etag
+        );
+        assert_ne!(
+            graph_etag(
+                "0123456789abcdef",
+                &params(&[("degree", "1"), ("format", "dot")])
+            ),
+            etag
+        );
+    }
+
+    #[rstest]
+    fn test_diff(song_graph: DiGraph<GraphNode, EdgeData>) {
+        let previous = snapshot(&song_graph);
+        let mut updated = song_graph.clone();
+        updated.remove_node(NodeIndex::new(2));
+        let song_4 = updated.add_node(GraphNode::new(
+            1,
+            SongData::new(4, "Foobar 2".into(), "Barfoo".into()),
+        ));
+        updated.add_edge(
+            NodeIndex::new(0),
+            song_4,
+            EdgeData::new(RelationshipType::RemixOf),
+        );
+        let current = snapshot(&updated);
+        let result = diff(&previous, &current);
+        assert_eq!(
+            result.added,
+            vec![
+                GraphElement::Node(Box::new(current.nodes[2].clone())),
+                GraphElement::Edge(current.edges[1]),
+            ]
+        );
+        assert_eq!(
+            result.removed,
+            vec![
+                GraphElement::Node(Box::new(previous.nodes[2].clone())),
+                GraphElement::Edge(previous.edges[1]),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_diff_unchanged(song_graph: DiGraph<GraphNode, EdgeData>) {
+        let current = snapshot(&song_graph);
+        assert_eq!(diff(&current, &current), GraphDelta::default());
+    }
+
+    #[rstest]
+    fn test_degree_snapshots(mut song_graph: DiGraph<GraphNode, EdgeData>) {
+        let song_4 = song_graph.add_node(GraphNode::new(
+            2,
+            SongData::new(4, "Foobar 2".into(), "Barfoo".into()),
+        ));
+        song_graph.add_edge(
+            NodeIndex::new(1),
+            song_4,
+            EdgeData::new(RelationshipType::SampledIn),
+        );
+        let result = degree_snapshots(&song_graph, 2);
+        assert_eq!(
+            result.iter().map(|s| s.degree).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(result[0].graph.node_count(), 3);
+        assert_eq!(result[0].graph.edge_count(), 2);
+        assert_eq!(result[1].graph.node_count(), 4);
+        assert_eq!(result[1].graph.edge_count(), 3);
+    }
+
+    #[rstest]
+    fn test_degree_snapshots_zero(song_graph: DiGraph<GraphNode, EdgeData>) {
+        assert!(degree_snapshots(&song_graph, 0).is_empty());
+    }
+
+    #[rstest]
+    #[case("Foobar", "Foobar")]
+    #[case("Foo, Bar", "\"Foo, Bar\"")]
+    #[case("Foo \"Bar\"", "\"Foo \"\"Bar\"\"\"")]
+    #[case("Foo\nBar", "\"Foo\nBar\"")]
+    fn test_csv_field(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(csv_field(input), expected);
+    }
+
+    #[rstest]
+    #[case("Foobar", "Foobar")]
+    #[case("<Foo & \"Bar\">", "&lt;Foo &amp; &quot;Bar&quot;&gt;")]
+    #[case("Don't", "Don&apos;t")]
+    fn test_xml_escape(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(xml_escape(input), expected);
+    }
+
+    #[rstest]
+    fn test_to_graphml(mut song_graph: DiGraph<GraphNode, EdgeData>) {
+        song_graph[NodeIndex::new(2)].song.title = "Foo & <Bar>".into();
+        let result = to_graphml(&song_graph);
+        assert!(result.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<graphml"));
+        assert!(result.ends_with("</graphml>\n"));
+        assert_eq!(result.matches("<node ").count(), song_graph.node_count());
+        assert_eq!(result.matches("<edge ").count(), song_graph.edge_count());
+        assert!(result.contains("<data key=\"title\">Foo &amp; &lt;Bar&gt;</data>"));
+        assert!(!result.contains("Foo & <Bar>"));
+        assert!(result.contains("<edge id=\"e0\" source=\"n1\" target=\"n2\">"));
+        assert!(result.contains("<data key=\"relationship\">samples</data>"));
+    }
+
+    #[rstest]
+    fn test_to_adjacency(song_graph: DiGraph<GraphNode, EdgeData>) {
+        let result = to_adjacency(&song_graph);
+        let ids = result.nodes.iter().map(|song| song.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(
+            result.edges,
+            vec![
+                AdjacencyEdge {
+                    source: 1,
+                    target: 2,
+                    relationship_type: RelationshipType::Samples,
+                },
+                AdjacencyEdge {
+                    source: 1,
+                    target: 3,
+                    relationship_type: RelationshipType::Interpolates,
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(result.edges[0]).unwrap(),
+            serde_json::json!({"source": 1, "target": 2, "type": "samples"})
+        );
+    }
+
+    #[rstest]
+    fn test_csv_rows(mut song_graph: DiGraph<GraphNode, EdgeData>) {
+        song_graph[NodeIndex::new(2)].song.title = "Foo, Bar".into();
+        let result = csv_rows(song_graph).collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec![
+                CSV_HEADER.to_string(),
+                "1,Foobar,Barfoo,2,Foobar,Barfoo,samples\n".to_string(),
+                "1,Foobar,Barfoo,3,\"Foo, Bar\",Barfoo,interpolates\n".to_string(),
+            ]
+        );
+    }
+}
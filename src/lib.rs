@@ -12,9 +12,25 @@
 
 pub mod cli;
 pub use cli::*;
+pub mod coalesce;
+pub use coalesce::*;
+pub mod disk;
+pub use disk::*;
+pub mod export;
+pub use export::*;
+pub mod recording;
+pub use recording::*;
 pub mod state;
 pub use state::*;
+pub mod timing;
+pub use timing::*;
+pub mod graph;
+pub use graph::*;
+pub mod limit;
+pub use limit::*;
 pub mod routes;
 pub use routes::*;
 pub mod models;
 pub use models::*;
+pub mod trace;
+pub use trace::*;
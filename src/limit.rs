@@ -0,0 +1,205 @@
+//! Per-client rate limiting.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, State as AxumState},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{HeaderMap, Request, StatusCode};
+
+/// Header set by proxies with the address of the original client.
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// A fixed rate limiting window for a single client.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    /// When the window started.
+    start: Instant,
+    /// Requests made during the window.
+    count: u64,
+}
+
+/// Limits how many requests each client can make in a period.
+#[derive(Debug, Clone)]
+pub struct ClientRateLimiter {
+    /// Requests allowed per client per period.
+    rate: u64,
+    /// Length of a rate limiting window.
+    period: Duration,
+    /// Number of trusted proxies in front of the API, each appending to `X-Forwarded-For`.
+    trusted_proxies: usize,
+    /// The current window of each client.
+    clients: Arc<Mutex<HashMap<IpAddr, Window>>>,
+}
+
+impl ClientRateLimiter {
+    /// Create a new per-client rate limiter.
+    ///
+    /// # Args
+    ///
+    /// * `rate` - Requests allowed per client per period.
+    /// * `period` - Length of a rate limiting window.
+    /// * `trusted_proxies` - Number of trusted proxies in front of the API, each appending the
+    ///   address it received the request from to the `X-Forwarded-For` header.
+    ///   Zero ignores the header, identifying clients by the connected peer.
+    ///
+    /// # Returns
+    ///
+    /// The rate limiter.
+    pub fn new(rate: u64, period: Duration, trusted_proxies: usize) -> Self {
+        Self {
+            rate,
+            period,
+            trusted_proxies,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Work out which client made a request.
+    /// Clients can put anything at the start of `X-Forwarded-For`, so only the entry appended
+    /// by the outermost trusted proxy is used: the one as many entries from the right
+    /// as there are trusted proxies.
+    ///
+    /// # Args
+    ///
+    /// * `headers` - The request headers.
+    /// * `peer` - The address of the connected peer, if known.
+    ///
+    /// # Returns
+    ///
+    /// The client address. Unidentified clients share the unspecified address.
+    pub fn client_ip(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> IpAddr {
+        let forwarded = self
+            .trusted_proxies
+            .checked_sub(1)
+            .and_then(|hops| {
+                headers
+                    .get(FORWARDED_FOR_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.rsplit(',').nth(hops))
+            })
+            .and_then(|v| v.trim().parse().ok());
+        forwarded
+            .or(peer)
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+
+    /// Record a request from a client.
+    ///
+    /// # Args
+    ///
+    /// * `client` - The client address.
+    /// * `now` - When the request was made.
+    ///
+    /// # Returns
+    ///
+    /// Whether the request is within the client's limit.
+    pub fn check(&self, client: IpAddr, now: Instant) -> bool {
+        let mut clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+        if !clients.contains_key(&client) {
+            clients.retain(|_, w| now.duration_since(w.start) < self.period);
+        }
+        let window = clients.entry(client).or_insert(Window {
+            start: now,
+            count: 0,
+        });
+        if now.duration_since(window.start) >= self.period {
+            *window = Window {
+                start: now,
+                count: 0,
+            };
+        }
+        window.count += 1;
+        window.count <= self.rate
+    }
+}
+
+/// Middleware rejecting requests from clients over their rate limit.
+///
+/// # Args
+///
+/// * `limiter` - The per-client rate limiter.
+/// * `request` - The incoming request.
+/// * `next` - The rest of the middleware stack.
+///
+/// # Returns
+///
+/// The response, or `429 Too Many Requests` if the client is over its limit.
+pub async fn limit_clients<B>(
+    AxumState(limiter): AxumState<ClientRateLimiter>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client = limiter.client_ip(request.headers(), peer);
+    if limiter.check(client, Instant::now()) {
+        next.run(request).await
+    } else {
+        (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+    use rstest::*;
+
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))
+    }
+
+    #[rstest]
+    #[case(0, Some("10.0.0.2"), Some(ip(1)), ip(1))]
+    #[case(1, Some("10.0.0.2"), Some(ip(1)), ip(2))]
+    #[case(1, Some("10.0.0.3, 10.0.0.2"), Some(ip(1)), ip(2))]
+    #[case(2, Some("10.0.0.4, 10.0.0.3, 10.0.0.2"), Some(ip(1)), ip(3))]
+    #[case(2, Some("10.0.0.2"), Some(ip(1)), ip(1))]
+    #[case(1, Some("garbage"), Some(ip(1)), ip(1))]
+    #[case(1, None, Some(ip(1)), ip(1))]
+    #[case(0, None, None, IpAddr::V4(Ipv4Addr::UNSPECIFIED))]
+    fn test_client_ip(
+        #[case] trusted_proxies: usize,
+        #[case] forwarded_for: Option<&'static str>,
+        #[case] peer: Option<IpAddr>,
+        #[case] expected: IpAddr,
+    ) {
+        let limiter = ClientRateLimiter::new(1, Duration::from_secs(60), trusted_proxies);
+        let mut headers = HeaderMap::new();
+        if let Some(value) = forwarded_for {
+            headers.insert(FORWARDED_FOR_HEADER, HeaderValue::from_static(value));
+        }
+        assert_eq!(limiter.client_ip(&headers, peer), expected);
+    }
+
+    #[rstest]
+    fn test_check_clients_independent() {
+        let limiter = ClientRateLimiter::new(2, Duration::from_secs(60), 0);
+        let now = Instant::now();
+        let result = [ip(1), ip(1), ip(1), ip(2), ip(2), ip(2)]
+            .into_iter()
+            .map(|client| limiter.check(client, now))
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec![true, true, false, true, true, false]);
+    }
+
+    #[rstest]
+    fn test_check_window_resets() {
+        let limiter = ClientRateLimiter::new(1, Duration::from_secs(60), 0);
+        let now = Instant::now();
+        assert!(limiter.check(ip(1), now));
+        assert!(!limiter.check(ip(1), now + Duration::from_secs(59)));
+        assert!(limiter.check(ip(1), now + Duration::from_secs(60)));
+    }
+}
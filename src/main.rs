@@ -1,18 +1,23 @@
-use std::{env::var, error::Error, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    env::var,
+    error::Error,
+    fs::{create_dir_all, read_to_string},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
 
-use axum::{error_handling::HandleErrorLayer, routing::get, BoxError, Router, Server};
+use axum::Server;
 use clap::Parser;
-use genius_rust::Genius;
-use http::{Method, StatusCode};
-use redis::Client;
-use tower::{buffer::BufferLayer, limit::rate::RateLimitLayer, ServiceBuilder};
-use tower_http::{
-    cors::{Any, CorsLayer},
-    trace::TraceLayer,
-};
+use tracing::error;
 use tracing_subscriber::fmt;
 
-use sample_graph_api::{graph, search, version, AppState, Args};
+use sample_graph_api::{
+    router, AppState, Args, ClientRateLimiter, DiskCache, GeniusRetry, GraphFormat, Recording,
+    RedisRetry, RelationshipType, SampledOnResponse, State, DEFAULT_DEGREE, DEFAULT_DEGREE_CEILING,
+    DEFAULT_INTERESTING_NODES, DEFAULT_POOL_SIZE,
+};
 
 #[cfg(not(tarpaulin_include))]
 #[tokio::main]
@@ -21,33 +26,183 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let args = Args::parse();
 
-    let genius_client = Genius::new(var("GENIUS_KEY")?);
-    let redis_client = Client::open(var("DATABASE_URL")?)?;
-    let shared_state = Arc::new(AppState::new(
-        genius_client,
-        redis_client,
+    let mut app_state = AppState::new(
+        var("GENIUS_KEY")?,
+        &var("DATABASE_URL")?,
+        var("REDIS_POOL_SIZE")
+            .ok()
+            .map(|size| size.parse::<usize>())
+            .transpose()?
+            .unwrap_or(DEFAULT_POOL_SIZE),
         var("REDIS_KEY_EXPIRY")?.parse::<usize>()?,
-    ));
-
-    let cors = CorsLayer::new()
-        .allow_methods(Method::GET)
-        .allow_origin(Any);
-    let route_layers = ServiceBuilder::new()
-        .layer(HandleErrorLayer::new(|err: BoxError| async move {
-            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
-        }))
-        .layer(BufferLayer::new(1024))
-        .layer(RateLimitLayer::new(20, Duration::from_secs(60)))
-        .layer(TraceLayer::new_for_http())
-        .layer(cors);
-    let router = Router::new()
-        .route("/search", get(search))
-        .route("/graph/:song_id", get(graph))
-        .route("/version", get(version))
-        .layer(route_layers)
-        .with_state(shared_state);
+    )?
+    .with_genius_timeout(
+        var("GENIUS_TIMEOUT_MS")
+            .ok()
+            .map(|ms| ms.parse::<u64>().map(Duration::from_millis))
+            .transpose()?,
+    )
+    .with_graph_timeout(
+        var("GRAPH_TIMEOUT_MS")
+            .ok()
+            .map(|ms| ms.parse::<u64>().map(Duration::from_millis))
+            .transpose()?,
+    )
+    .with_relevant_types(
+        var("RELEVANT_TYPES")
+            .map(|types| RelationshipType::parse_list(&types))
+            .unwrap_or_else(|_| Ok(RelationshipType::default_relevant()))?,
+    )
+    .with_genius_budget(
+        var("GENIUS_CALL_BUDGET")
+            .ok()
+            .map(|budget| budget.parse::<usize>())
+            .transpose()?,
+    );
+    if let Ok(path) = var("DISK_CACHE_PATH") {
+        app_state = app_state.with_disk_cache(DiskCache::new(path)?);
+    }
+    app_state = app_state.with_stats_expiry(
+        var("SONG_STATS_EXPIRY")
+            .ok()
+            .map(|expiry| expiry.parse::<usize>())
+            .transpose()?,
+    );
+    app_state = app_state.with_key_expiries(
+        var("SONG_EXPIRY")
+            .ok()
+            .map(|expiry| expiry.parse::<usize>())
+            .transpose()?,
+        var("RELATIONSHIPS_EXPIRY")
+            .ok()
+            .map(|expiry| expiry.parse::<usize>())
+            .transpose()?,
+        var("SEARCH_EXPIRY")
+            .ok()
+            .map(|expiry| expiry.parse::<usize>())
+            .transpose()?,
+    );
+    let max_degree = var("MAX_DEGREE")
+        .ok()
+        .map(|degree| degree.parse::<u8>())
+        .transpose()?;
+    let degree_ceiling = var("DEGREE_CEILING")
+        .map(|degree| degree.parse::<u8>())
+        .unwrap_or(Ok(DEFAULT_DEGREE_CEILING))?;
+    let highest_degree =
+        max_degree.map_or(degree_ceiling, |max_degree| max_degree.min(degree_ceiling));
+    let default_degree = match var("DEFAULT_DEGREE") {
+        Ok(degree) => {
+            let degree = degree.parse::<u8>()?;
+            if !(1..=highest_degree).contains(&degree) {
+                return Err(
+                    format!("DEFAULT_DEGREE must be between 1 and {}", highest_degree).into(),
+                );
+            }
+            degree
+        }
+        Err(_) => DEFAULT_DEGREE.min(highest_degree),
+    };
+    if let Some(max_degree) = max_degree {
+        app_state = app_state.with_max_degree(max_degree);
+    }
+    app_state = app_state
+        .with_degree_ceiling(degree_ceiling)
+        .with_default_degree(default_degree);
+    app_state = app_state.with_max_nodes(
+        var("MAX_GRAPH_NODES")
+            .ok()
+            .map(|max| max.parse::<usize>())
+            .transpose()?,
+    );
+    app_state = app_state.with_interesting_nodes(
+        var("AUTO_DEGREE_MIN_NODES")
+            .map(|min| min.parse::<usize>())
+            .unwrap_or(Ok(*DEFAULT_INTERESTING_NODES.start()))?
+            ..=var("AUTO_DEGREE_MAX_NODES")
+                .map(|max| max.parse::<usize>())
+                .unwrap_or(Ok(*DEFAULT_INTERESTING_NODES.end()))?,
+    );
+    let default_retry = RedisRetry::default();
+    app_state = app_state.with_redis_retry(
+        RedisRetry {
+            attempts: var("REDIS_RETRIES")
+                .map(|retries| retries.parse::<usize>().map(|retries| retries + 1))
+                .unwrap_or(Ok(default_retry.attempts))?,
+            backoff: var("REDIS_RETRY_BACKOFF_MS")
+                .map(|ms| ms.parse::<u64>().map(Duration::from_millis))
+                .unwrap_or(Ok(default_retry.backoff))?,
+        },
+        var("REDIS_FALLBACK")
+            .map(|fallback| fallback.parse::<bool>())
+            .unwrap_or(Ok(false))?,
+    );
+    let default_genius_retry = GeniusRetry::default();
+    app_state = app_state.with_genius_retry(GeniusRetry {
+        attempts: var("GENIUS_RETRIES")
+            .map(|retries| retries.parse::<usize>().map(|retries| retries + 1))
+            .unwrap_or(Ok(default_genius_retry.attempts))?,
+        backoff: var("GENIUS_RETRY_BACKOFF_MS")
+            .map(|ms| ms.parse::<u64>().map(Duration::from_millis))
+            .unwrap_or(Ok(default_genius_retry.backoff))?,
+    });
+    if let Ok(path) = var("RECORDING_PATH") {
+        create_dir_all(&path)?;
+        app_state = app_state.with_recording_dir(path.into());
+    }
+    if let Ok(path) = var("REPLAY_PATH") {
+        app_state = app_state.with_replay(Recording::load(path)?);
+    }
+    if let Ok(format) = var("DEFAULT_GRAPH_FORMAT") {
+        app_state = app_state.with_default_format(GraphFormat::parse(&format)?);
+    }
+    if let Ok(aliases) = var("RELATIONSHIP_ALIASES") {
+        app_state = app_state.with_relationship_aliases(RelationshipType::parse_aliases(&aliases)?);
+    }
+    if let Ok(token) = var("ADMIN_TOKEN") {
+        app_state = app_state.with_admin_token(token);
+    }
+    if let Ok(path) = var("ALLOWLIST_PATH") {
+        app_state = app_state.with_allowlist(
+            read_to_string(path)?
+                .split_whitespace()
+                .map(|id| id.parse::<u32>())
+                .collect::<Result<HashSet<_>, _>>()?,
+        );
+    }
+    let shared_state = Arc::new(app_state);
+
+    if args.validate_credentials {
+        if let Err(e) = shared_state.validate_credentials().await {
+            error!("Genius credentials were rejected, check GENIUS_KEY - {}", e);
+            return Err(e.into());
+        }
+    }
+
+    let sampler = SampledOnResponse::new(
+        var("TRACE_SAMPLE_RATE")
+            .map(|rate| rate.parse::<u64>())
+            .unwrap_or(Ok(1))?,
+        var("TRACE_SLOW_MS")
+            .ok()
+            .map(|ms| ms.parse::<u64>().map(Duration::from_millis))
+            .transpose()?,
+    );
+    let limiter = ClientRateLimiter::new(
+        var("CLIENT_RATE_LIMIT")
+            .map(|rate| rate.parse::<u64>())
+            .unwrap_or(Ok(20))?,
+        Duration::from_secs(60),
+        match var("TRUSTED_PROXIES") {
+            Ok(proxies) => proxies.parse::<usize>()?,
+            Err(_) => usize::from(var("TRUST_FORWARDED_FOR").is_ok_and(|trust| trust == "true")),
+        },
+    );
     Server::bind(&args.address().parse()?)
-        .serve(router.into_make_service())
+        .serve(
+            router(shared_state, sampler, limiter)
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        )
         .await?;
 
     Ok(())
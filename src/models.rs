@@ -1,13 +1,23 @@
 //! Various helper structs for organizing data.
 
-use std::fmt::Debug;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
 
-use genius_rust::{search::Hit, song::Song as GeniusSong};
-use petgraph::graph::NodeIndex;
+use genius_rust::{
+    album::Album,
+    search::Hit,
+    song::{Artist, Song as GeniusSong},
+};
+use petgraph::graph::{DiGraph, NodeIndex};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 /// Possible relationships between songs.
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Eq, Hash, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum RelationshipType {
     /// Samples another song.
@@ -59,21 +69,245 @@ impl<S: AsRef<str>> From<S> for RelationshipType {
 }
 
 impl RelationshipType {
+    /// Returns the Genius name of the relationship type.
+    ///
+    /// # Returns
+    ///
+    /// The name, e.g. `sampled_in`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Samples => "samples",
+            Self::SampledIn => "sampled_in",
+            Self::Interpolates => "interpolates",
+            Self::InterpolatedBy => "interpolated_by",
+            Self::CoverOf => "cover_of",
+            Self::CoveredBy => "covered_by",
+            Self::RemixOf => "remix_of",
+            Self::RemixedBy => "remixed_by",
+            Self::LiveVersionOf => "live_version_of",
+            Self::PerformedLiveAs => "performed_live_as",
+            Self::TranslationOf => "translation_of",
+            Self::Translations => "translations",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Returns the numeric code of the relationship type, used to shrink JSON payloads.
+    /// Codes are stable: new types get new codes rather than reusing existing ones.
+    ///
+    /// # Returns
+    ///
+    /// The code, e.g. `0` for `samples`.
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Samples => 0,
+            Self::SampledIn => 1,
+            Self::Interpolates => 2,
+            Self::InterpolatedBy => 3,
+            Self::CoverOf => 4,
+            Self::CoveredBy => 5,
+            Self::RemixOf => 6,
+            Self::RemixedBy => 7,
+            Self::LiveVersionOf => 8,
+            Self::PerformedLiveAs => 9,
+            Self::TranslationOf => 10,
+            Self::Translations => 11,
+            Self::Unknown => 12,
+        }
+    }
+
+    /// Returns the relationship type with a numeric code.
+    /// See [`RelationshipType::code`].
+    ///
+    /// # Args
+    ///
+    /// * `code` - The numeric code.
+    ///
+    /// # Returns
+    ///
+    /// The relationship type, if the code is known.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Samples),
+            1 => Some(Self::SampledIn),
+            2 => Some(Self::Interpolates),
+            3 => Some(Self::InterpolatedBy),
+            4 => Some(Self::CoverOf),
+            5 => Some(Self::CoveredBy),
+            6 => Some(Self::RemixOf),
+            7 => Some(Self::RemixedBy),
+            8 => Some(Self::LiveVersionOf),
+            9 => Some(Self::PerformedLiveAs),
+            10 => Some(Self::TranslationOf),
+            11 => Some(Self::Translations),
+            12 => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of every relationship type by numeric code.
+    ///
+    /// # Returns
+    ///
+    /// The legend of codes, e.g. `0` to `samples`.
+    pub fn legend() -> BTreeMap<u8, &'static str> {
+        (0..=u8::MAX)
+            .map_while(Self::from_code)
+            .map(|relationship_type| (relationship_type.code(), relationship_type.as_str()))
+            .collect()
+    }
+
     /// Determines if a relationship is relevant to the web API.
-    /// Currently just samples (both ways).
+    ///
+    /// # Args
+    ///
+    /// * `relevant_types` - The relevant relationship types,
+    ///   e.g. [`RelationshipType::default_relevant`].
     ///
     /// # Returns
     ///
     /// Whether the relationship type is relevant.
-    pub fn is_relevant(&self) -> bool {
-        matches!(
-            self,
-            Self::SampledIn | Self::Samples | Self::Interpolates | Self::InterpolatedBy
-        )
+    pub fn is_relevant(&self, relevant_types: &HashSet<Self>) -> bool {
+        relevant_types.contains(self)
+    }
+
+    /// Returns the relationship types that are relevant unless configured otherwise.
+    /// Currently samples and interpolations (both ways).
+    ///
+    /// # Returns
+    ///
+    /// The default set of relevant relationship types.
+    pub fn default_relevant() -> HashSet<Self> {
+        HashSet::from([
+            Self::Samples,
+            Self::SampledIn,
+            Self::Interpolates,
+            Self::InterpolatedBy,
+        ])
+    }
+
+    /// Parse a comma-separated list of relationship types.
+    /// Whitespace around each entry and empty entries are ignored.
+    ///
+    /// # Args
+    ///
+    /// * `list` - The list of relationship types, e.g. `samples,sampled_in`.
+    ///
+    /// # Returns
+    ///
+    /// The set of relationship types, or the first entry that is not a known type.
+    pub fn parse_list(list: &str) -> Result<HashSet<Self>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| match Self::from(entry) {
+                Self::Unknown => Err(format!("Unknown relationship type: {}", entry)),
+                rt => Ok(rt),
+            })
+            .collect()
+    }
+
+    /// Parse a comma-separated list of aliases for relationship types.
+    /// Whitespace around each entry and empty entries are ignored.
+    ///
+    /// # Args
+    ///
+    /// * `list` - The list of aliases, e.g. `sampled_from=samples,remix_by=remixed_by`.
+    ///
+    /// # Returns
+    ///
+    /// The relationship type of each alias, or the first entry that is not a valid alias.
+    pub fn parse_aliases(list: &str) -> Result<HashMap<String, Self>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (alias, target) = entry
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid relationship alias: {}", entry))?;
+                match Self::from(target.trim()) {
+                    Self::Unknown => Err(format!("Unknown relationship type: {}", target.trim())),
+                    rt => Ok((alias.trim().to_string(), rt)),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the same relationship seen from the other song.
+    ///
+    /// # Returns
+    ///
+    /// The inverse relationship type.
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::Samples => Self::SampledIn,
+            Self::SampledIn => Self::Samples,
+            Self::Interpolates => Self::InterpolatedBy,
+            Self::InterpolatedBy => Self::Interpolates,
+            Self::CoverOf => Self::CoveredBy,
+            Self::CoveredBy => Self::CoverOf,
+            Self::RemixOf => Self::RemixedBy,
+            Self::RemixedBy => Self::RemixOf,
+            Self::LiveVersionOf => Self::PerformedLiveAs,
+            Self::PerformedLiveAs => Self::LiveVersionOf,
+            Self::TranslationOf => Self::Translations,
+            Self::Translations => Self::TranslationOf,
+            Self::Unknown => Self::Unknown,
+        }
+    }
+
+    /// Returns the family of a relationship, ignoring its direction.
+    /// The family is named after the forward relationship (e.g. `SampledIn` is a `Samples`).
+    ///
+    /// # Returns
+    ///
+    /// The forward relationship type.
+    pub fn family(&self) -> Self {
+        match self {
+            Self::SampledIn => Self::Samples,
+            Self::InterpolatedBy => Self::Interpolates,
+            Self::CoveredBy => Self::CoverOf,
+            Self::RemixedBy => Self::RemixOf,
+            Self::PerformedLiveAs => Self::LiveVersionOf,
+            Self::Translations => Self::TranslationOf,
+            other => *other,
+        }
+    }
+
+    /// Returns how strongly a relationship ties two songs together, ignoring its direction.
+    /// Samples are strongest, then interpolations, remixes, covers, live versions and translations.
+    ///
+    /// # Returns
+    ///
+    /// The strength, from 0 for `Unknown` up to 5.
+    pub fn weight(&self) -> u32 {
+        match self.family() {
+            Self::Samples => 5,
+            Self::Interpolates => 4,
+            Self::RemixOf => 3,
+            Self::CoverOf => 2,
+            Self::LiveVersionOf | Self::TranslationOf => 1,
+            _ => 0,
+        }
+    }
+
+    /// Returns how far apart a relationship puts two songs, the inverse of its [`weight`](Self::weight).
+    ///
+    /// # Returns
+    ///
+    /// The distance, or `None` if the relationship has no weight.
+    pub fn distance(&self) -> Option<f64> {
+        match self.weight() {
+            0 => None,
+            weight => Some(1.0 / f64::from(weight)),
+        }
     }
 }
 
 /// Relevant song data.
+///
+/// The derived `PartialEq` compares every field, including optional metadata.
+/// Use [`SongData::id_eq`] to check whether two values describe the same song.
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct SongData {
     /// Genius ID of the song.
@@ -82,6 +316,24 @@ pub struct SongData {
     pub title: String,
     /// Artist's name who made the song.
     pub artist_name: String,
+    /// Number of Genius pageviews, useful for sizing nodes by popularity.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pageviews: Option<u64>,
+    /// Name of the album the song is on, useful for grouping nodes by album.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// Release date as given by Genius, e.g. `1971-03-05`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<String>,
+    /// Title in its original script, when Genius also gives a romanized or translated form.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_title: Option<String>,
+    /// Title in Latin script, when Genius gives one alongside a non-Latin title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub romanized_title: Option<String>,
+    /// Small image for the song, its art or else its artist's image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
 }
 
 impl SongData {
@@ -101,27 +353,453 @@ impl SongData {
             id,
             title,
             artist_name,
+            pageviews: None,
+            album: None,
+            release_date: None,
+            original_title: None,
+            romanized_title: None,
+            thumbnail_url: None,
         }
     }
+
+    /// Return the year the song was released.
+    /// The year is the first four-digit number in the release date,
+    /// so partial dates such as `1971` or `March 1971` are understood too.
+    ///
+    /// # Returns
+    ///
+    /// The release year, if the song has a release date that includes one.
+    pub fn release_year(&self) -> Option<i32> {
+        self.release_date
+            .as_deref()?
+            .split(|c: char| !c.is_ascii_digit())
+            .find(|part| part.len() == 4)?
+            .parse()
+            .ok()
+    }
+}
+
+/// Split a Genius title written as `original (alternate)` into its two forms.
+/// Genius titles non-Latin songs this way, e.g. `夜に駆ける (Yoru ni Kakeru)`.
+///
+/// # Args
+///
+/// * `title` - The Genius title, without featured artists.
+///
+/// # Returns
+///
+/// The title in its original script and its Latin alternate form, if the title has both.
+fn title_forms(title: &str) -> Option<(&str, &str)> {
+    let (original, alternate) = title.strip_suffix(')')?.rsplit_once(" (")?;
+    let original = original.trim();
+    let alternate = alternate.trim();
+    if original.chars().any(|c| c.is_alphabetic() && !c.is_ascii())
+        && !alternate.is_empty()
+        && alternate.is_ascii()
+    {
+        Some((original, alternate))
+    } else {
+        None
+    }
+}
+
+impl SongData {
+    /// Split the song data into its stable core and its frequently changing stats.
+    ///
+    /// # Returns
+    ///
+    /// The song data without stats, and the stats.
+    pub fn split_stats(mut self) -> (Self, SongStats) {
+        let stats = SongStats {
+            pageviews: self.pageviews.take(),
+        };
+        (self, stats)
+    }
+
+    /// Replace the frequently changing stats of the song data.
+    ///
+    /// # Args
+    ///
+    /// * `stats` - The song stats.
+    ///
+    /// # Returns
+    ///
+    /// The song data.
+    pub fn with_stats(mut self, stats: SongStats) -> Self {
+        self.pageviews = stats.pageviews;
+        self
+    }
+
+    /// Remove every field not requested from serialized song data.
+    /// Requested names that are not song data fields are ignored.
+    ///
+    /// # Args
+    ///
+    /// * `song` - The serialized song data.
+    /// * `fields` - The names of the fields to keep.
+    pub fn retain_fields(song: &mut Value, fields: &HashSet<String>) {
+        if let Value::Object(song) = song {
+            song.retain(|name, _| fields.contains(name));
+        }
+    }
+
+    /// Determines if two song data values describe the same Genius song.
+    /// Unlike `==`, optional metadata is ignored.
+    ///
+    /// # Args
+    ///
+    /// * `other` - The other song data.
+    ///
+    /// # Returns
+    ///
+    /// Whether both values have the same Genius ID.
+    pub fn id_eq(&self, other: &SongData) -> bool {
+        self.id == other.id
+    }
+}
+
+/// Marker in the URL of the placeholder art Genius gives songs without their own.
+const DEFAULT_ART_MARKER: &str = "default_cover_image";
+
+/// Choose a thumbnail for a Genius song.
+/// Songs without art fall back to their artist's image, then their artist's header image.
+///
+/// # Args
+///
+/// * `song` - The Genius song.
+///
+/// # Returns
+///
+/// The URL of the thumbnail, if there is any image.
+fn thumbnail_url(song: &GeniusSong) -> Option<String> {
+    [
+        &song.song_art_image_thumbnail_url,
+        &song.primary_artist.image_url,
+        &song.primary_artist.header_image_url,
+    ]
+    .into_iter()
+    .find(|url| !url.is_empty() && !url.contains(DEFAULT_ART_MARKER))
+    .cloned()
 }
 
 impl From<Hit> for SongData {
     fn from(value: Hit) -> Self {
-        Self::new(
-            value.result.id,
-            value.result.title_with_featured,
-            value.result.primary_artist.name,
-        )
+        Self {
+            thumbnail_url: thumbnail_url(&value.result),
+            ..Self::new(
+                value.result.id,
+                value.result.title_with_featured,
+                value.result.primary_artist.name,
+            )
+        }
     }
 }
 
 impl From<GeniusSong> for SongData {
     fn from(value: GeniusSong) -> Self {
-        Self::new(
-            value.id,
-            value.title_with_featured,
-            value.primary_artist.name,
-        )
+        let title_forms = title_forms(&value.title);
+        Self {
+            thumbnail_url: thumbnail_url(&value),
+            pageviews: value.stats.pageviews.map(u64::from),
+            album: value.album.map(|a| a.name),
+            release_date: value.release_date,
+            original_title: title_forms.map(|(original, _)| original.to_string()),
+            romanized_title: title_forms.map(|(_, romanized)| romanized.to_string()),
+            ..Self::new(
+                value.id,
+                value.title_with_featured,
+                value.primary_artist.name,
+            )
+        }
+    }
+}
+
+/// Artist data from Genius.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ArtistData {
+    /// Genius ID of the artist.
+    pub id: u32,
+    /// Name of the artist.
+    pub name: String,
+}
+
+impl From<Artist> for ArtistData {
+    fn from(value: Artist) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+        }
+    }
+}
+
+/// Album data from Genius.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct AlbumData {
+    /// Genius ID of the album.
+    pub id: u32,
+    /// Name of the album.
+    pub name: String,
+    /// The album's primary artist.
+    pub artist: ArtistData,
+}
+
+impl From<Album> for AlbumData {
+    fn from(value: Album) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            artist: value.artist.into(),
+        }
+    }
+}
+
+/// An artist and their songs found on Genius.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ArtistSongs {
+    /// The artist.
+    pub artist: ArtistData,
+    /// The artist's songs.
+    pub songs: Vec<SongData>,
+}
+
+impl ArtistSongs {
+    /// Collect an artist's songs from the songs Genius lists for them.
+    /// Songs listed for the artist but led by another artist, such as features, are left out.
+    ///
+    /// # Args
+    ///
+    /// * `artist_id` - Genius ID of the artist.
+    /// * `listed` - The songs Genius lists for the artist.
+    ///
+    /// # Returns
+    ///
+    /// The artist and their songs, or `None` if none of the songs are led by the artist.
+    pub fn from_listed(artist_id: u32, listed: Vec<ListedSong>) -> Option<Self> {
+        let artist = listed
+            .iter()
+            .map(|song| &song.primary_artist)
+            .find(|artist| artist.id == artist_id)?
+            .clone();
+        let songs = listed
+            .into_iter()
+            .filter(|song| song.primary_artist.id == artist_id)
+            .map(SongData::from)
+            .collect();
+        Some(Self { artist, songs })
+    }
+}
+
+/// A song as Genius lists it among an artist's songs, with fewer fields than a full song.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ListedSong {
+    /// Genius ID of the song.
+    pub id: u32,
+    /// Title of the song, including any featured artists.
+    pub title_with_featured: String,
+    /// The artist leading the song.
+    pub primary_artist: ArtistData,
+    /// Small image of the song's art.
+    #[serde(default)]
+    pub song_art_image_thumbnail_url: Option<String>,
+}
+
+impl From<ListedSong> for SongData {
+    fn from(value: ListedSong) -> Self {
+        Self {
+            thumbnail_url: value.song_art_image_thumbnail_url,
+            ..Self::new(
+                value.id,
+                value.title_with_featured,
+                value.primary_artist.name,
+            )
+        }
+    }
+}
+
+/// One page of the songs Genius lists for an artist.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ListedSongs {
+    /// The songs on the page.
+    pub songs: Vec<ListedSong>,
+}
+
+/// The body of a Genius API response.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct GeniusResponse<T> {
+    /// The data the response carries.
+    pub response: T,
+}
+
+/// One page of song results from a search.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct SearchPage {
+    /// The songs on the page.
+    pub results: Vec<SongData>,
+    /// The page number, starting from 1.
+    pub page: usize,
+    /// Total number of songs found by the search, across every page.
+    pub total: usize,
+}
+
+/// Song data that changes frequently, cached separately from the rest of the song.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SongStats {
+    /// Number of Genius pageviews.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pageviews: Option<u64>,
+}
+
+/// Orderings for search results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSort {
+    /// Genius relevance order.
+    #[default]
+    Relevance,
+    /// Alphabetical by title.
+    Title,
+    /// Alphabetical by artist name.
+    Artist,
+}
+
+impl<S: AsRef<str>> From<S> for SearchSort {
+    fn from(value: S) -> Self {
+        match value.as_ref() {
+            "title" => Self::Title,
+            "artist" => Self::Artist,
+            _ => Self::Relevance,
+        }
+    }
+}
+
+impl SearchSort {
+    /// Sort search results in place.
+    /// Sorting is stable, so ties keep Genius relevance order.
+    ///
+    /// # Args
+    ///
+    /// * `songs` - Search results in Genius relevance order.
+    pub fn apply(&self, songs: &mut [SongData]) {
+        match self {
+            Self::Relevance => {}
+            Self::Title => songs.sort_by_cached_key(|song| song.title.to_lowercase()),
+            Self::Artist => songs.sort_by_cached_key(|song| song.artist_name.to_lowercase()),
+        }
+    }
+}
+
+/// Response formats for graphs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    /// Nodes and edges as JSON.
+    #[default]
+    Json,
+    /// One CSV row per edge.
+    Csv,
+    /// A standalone page rendering the graph.
+    Html,
+    /// RDF triples in Turtle.
+    Turtle,
+    /// A Graphviz digraph in the DOT language.
+    Dot,
+    /// A GraphML document, e.g. for Gephi.
+    GraphMl,
+    /// Songs and edges as JSON, keyed by Genius ID rather than node index.
+    Adjacency,
+}
+
+impl GraphFormat {
+    /// Parse the name of a graph format.
+    ///
+    /// # Args
+    ///
+    /// * `value` - The format name, e.g. `csv`.
+    ///
+    /// # Returns
+    ///
+    /// The graph format, or an error naming the unknown format.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "html" => Ok(Self::Html),
+            "turtle" => Ok(Self::Turtle),
+            "dot" => Ok(Self::Dot),
+            "graphml" => Ok(Self::GraphMl),
+            "adjacency" => Ok(Self::Adjacency),
+            other => Err(format!("Unknown graph format: {}", other)),
+        }
+    }
+
+    /// Pick the graph format an `Accept` header prefers.
+    /// Media types are ranked by their `q` weight, and ones without a graph format are skipped.
+    ///
+    /// # Args
+    ///
+    /// * `accept` - The `Accept` header value, e.g. `text/csv, application/json;q=0.5`.
+    ///
+    /// # Returns
+    ///
+    /// The preferred graph format, or `None` if the header names none.
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        let mut best: Option<(f32, Self)> = None;
+        for range in accept.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let format = match parts.next().unwrap_or_default() {
+                "application/json" => Self::Json,
+                "text/csv" => Self::Csv,
+                "text/html" => Self::Html,
+                "text/turtle" => Self::Turtle,
+                "text/vnd.graphviz" => Self::Dot,
+                "application/xml" | "application/graphml+xml" => Self::GraphMl,
+                _ => continue,
+            };
+            let weight = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map(|q| q.parse().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            if weight > 0.0 && best.map(|(best, _)| weight > best).unwrap_or(true) {
+                best = Some((weight, format));
+            }
+        }
+        best.map(|(_, format)| format)
+    }
+}
+
+/// Orderings for a song's relationships.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationshipOrder {
+    /// Strongest relationship type first, then by Genius ID.
+    #[default]
+    Strength,
+    /// The order Genius listed them in.
+    Insertion,
+}
+
+impl<S: AsRef<str>> From<S> for RelationshipOrder {
+    fn from(value: S) -> Self {
+        match value.as_ref() {
+            "insertion" => Self::Insertion,
+            _ => Self::Strength,
+        }
+    }
+}
+
+impl RelationshipOrder {
+    /// Sort relationships in place.
+    ///
+    /// # Args
+    ///
+    /// * `relationships` - Relationships in the order Genius listed them.
+    pub fn apply(&self, relationships: &mut [Relationship]) {
+        match self {
+            Self::Strength => {
+                relationships.sort_by_key(|r| (Reverse(r.relationship_type.weight()), r.song.id))
+            }
+            Self::Insertion => {}
+        }
     }
 }
 
@@ -132,6 +810,16 @@ pub struct Relationship {
     pub relationship_type: RelationshipType,
     /// The song that the relationship applies to.
     pub song: SongData,
+    /// The relationship type exactly as Genius reported it, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_type: Option<String>,
+    /// Genius ID of the song the relationship was fetched for.
+    /// Only set where relationships of several songs are listed together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_id: Option<u32>,
+    /// How many times Genius listed the relationship for the song, if more than once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listed: Option<u32>,
 }
 
 impl Relationship {
@@ -149,8 +837,84 @@ impl Relationship {
         Self {
             relationship_type,
             song,
+            raw_type: None,
+            from_id: None,
+            listed: None,
         }
     }
+
+    /// Keep the relationship type exactly as Genius reported it.
+    ///
+    /// # Args
+    ///
+    /// * `raw_type` - The raw Genius relationship type.
+    ///
+    /// # Returns
+    ///
+    /// The relationship.
+    pub fn with_raw_type(mut self, raw_type: &str) -> Self {
+        self.raw_type = Some(raw_type.to_string());
+        self
+    }
+
+    /// Record which song the relationship was fetched for.
+    ///
+    /// # Args
+    ///
+    /// * `from_id` - The Genius ID of the source song.
+    ///
+    /// # Returns
+    ///
+    /// The relationship.
+    pub fn with_from_id(mut self, from_id: u32) -> Self {
+        self.from_id = Some(from_id);
+        self
+    }
+
+    /// Return how many times Genius listed the relationship for the song.
+    ///
+    /// # Returns
+    ///
+    /// The number of listings, at least 1.
+    pub fn listings(&self) -> u32 {
+        self.listed.unwrap_or(1)
+    }
+
+    /// Remove repeated relationships, as Genius sometimes lists a song twice in one group.
+    /// Relationships are the same if they have the same type and Genius song ID,
+    /// and the first of each counts how many times it was listed.
+    ///
+    /// # Args
+    ///
+    /// * `relationships` - The relationships, possibly with repeats.
+    ///
+    /// # Returns
+    ///
+    /// The first of each distinct relationship with its count, in their original order.
+    pub fn dedup(relationships: Vec<Self>) -> Vec<Self> {
+        let mut positions: HashMap<(RelationshipType, u32), usize> = HashMap::new();
+        let mut deduped: Vec<Self> = Vec::new();
+        for relationship in relationships {
+            let key = (relationship.relationship_type, relationship.song.id);
+            if let Some(&position) = positions.get(&key) {
+                let first = &mut deduped[position];
+                first.listed = Some(first.listings() + relationship.listings());
+            } else {
+                positions.insert(key, deduped.len());
+                deduped.push(relationship);
+            }
+        }
+        deduped
+    }
+}
+
+/// A song together with its immediate relationships.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct SongDetail {
+    /// Genius song data.
+    pub song: SongData,
+    /// The relationships for the song.
+    pub relationships: Vec<Relationship>,
 }
 
 /// An item in a graph search queue.
@@ -185,34 +949,585 @@ impl QueueItem {
     }
 }
 
-/// Node data in a graph.
+/// The result of looking up data that may not exist.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Lookup<T> {
+    /// Whether the data exists.
+    pub found: bool,
+    /// The data, if it exists.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<T>,
+    /// When the data was cached, in seconds since the Unix epoch, if it was served from the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_at: Option<u64>,
+    /// How long ago the data was cached, in seconds, if it was served from the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age_seconds: Option<u64>,
+}
+
+impl<T> Lookup<T> {
+    /// Create a new lookup result.
+    ///
+    /// # Args
+    ///
+    /// * `data` - The data, if it exists.
+    ///
+    /// # Returns
+    ///
+    /// The lookup result.
+    pub fn new(data: Option<T>) -> Self {
+        Self {
+            found: data.is_some(),
+            data,
+            cached_at: None,
+            age_seconds: None,
+        }
+    }
+
+    /// Record how old the looked up data is.
+    ///
+    /// # Args
+    ///
+    /// * `cached_at` - When the data was cached, if it was served from the cache.
+    /// * `now` - The current time, in seconds since the Unix epoch.
+    ///
+    /// # Returns
+    ///
+    /// The lookup result with its age.
+    pub fn with_cached_at(mut self, cached_at: Option<u64>, now: u64) -> Self {
+        self.cached_at = cached_at;
+        self.age_seconds = cached_at.map(|at| now.saturating_sub(at));
+        self
+    }
+}
+
+/// A cached value, stored alongside when it was cached.
+/// Values cached before timestamps were stored are read without one.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(from = "CacheEntry<T>", bound(deserialize = "T: Deserialize<'de>"))]
+pub struct Cached<T> {
+    /// When the value was cached, in seconds since the Unix epoch, if known.
+    pub at: Option<u64>,
+    /// The cached value.
+    pub data: T,
+}
+
+/// A cache entry as stored, with or without a timestamp.
+/// Unknown fields are denied so a bare object is never mistaken for a timestamped one.
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum CacheEntry<T> {
+    /// A value stored alongside when it was cached.
+    Timestamped {
+        /// When the value was cached.
+        at: Option<u64>,
+        /// The cached value.
+        data: T,
+    },
+    /// A value cached before timestamps were stored.
+    Bare(T),
+}
+
+impl<T> From<CacheEntry<T>> for Cached<T> {
+    fn from(value: CacheEntry<T>) -> Self {
+        match value {
+            CacheEntry::Timestamped { at, data } => Self { at, data },
+            CacheEntry::Bare(data) => Self { at: None, data },
+        }
+    }
+}
+
+/// Where a piece of song data was retrieved from.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Provenance {
+    /// Retrieved from the Redis cache.
+    Cache,
+    /// Retrieved fresh from the Genius API.
+    Genius,
+}
+
+/// Optional behaviour when building a graph.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct GraphOptions {
+    /// Whether to label each node with its provenance.
+    pub provenance: bool,
+    /// Whether to skip neighbors made by the same artist as the center song.
+    pub exclude_center_artist: bool,
+    /// Only traverse relationships in this family, if set.
+    pub only: Option<RelationshipType>,
+    /// Only traverse relationships of these types, if set.
+    pub types: Option<BTreeSet<RelationshipType>>,
+    /// Genius IDs of songs that expand one degree beyond the maximum.
+    pub pin: BTreeSet<u32>,
+    /// Whether to label each node with the path from the center that led to it.
+    pub breadcrumbs: bool,
+    /// Most years a song's release may be from the center song's release, if set.
+    pub year_span: Option<u16>,
+    /// Lowest [`RelationshipType::weight`] an edge may have, if set.
+    pub min_weight: Option<u32>,
+    /// Whether to leave songs whose relationships fail to load unexpanded instead of failing.
+    pub best_effort: bool,
+    /// Farthest [`RelationshipType::distance`] along a path a song may be from the center, if set.
+    pub max_distance: Option<PathDistance>,
+    /// Most nodes the graph may contain, if set. The deployment's own limit still applies.
+    pub max_nodes: Option<usize>,
+    /// Whether to add the [`RelationshipType::inverse`] of each relationship found as an edge
+    /// back the other way, so songs can reach each other in both directions.
+    pub include_inverse: bool,
+    /// Whether to label each node with the album its song is on, if its album is cached.
+    pub include_albums: bool,
+}
+
+/// A distance along a path of relationships, summing their [`RelationshipType::distance`].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct PathDistance(f64);
+
+impl PathDistance {
+    /// Create a new path distance.
+    ///
+    /// # Args
+    ///
+    /// * `distance` - The distance.
+    ///
+    /// # Returns
+    ///
+    /// The path distance, or `None` if the distance is negative or not finite.
+    pub fn new(distance: f64) -> Option<Self> {
+        // Adding zero turns -0.0 into 0.0, so equal distances hash the same.
+        (distance.is_finite() && distance >= 0.0).then_some(Self(distance + 0.0))
+    }
+
+    /// Returns the distance.
+    ///
+    /// # Returns
+    ///
+    /// The distance.
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+// Path distances are always finite, so equality is reflexive.
+impl Eq for PathDistance {}
+
+impl Hash for PathDistance {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl GraphOptions {
+    /// Determines if a node's relationships should be explored.
+    ///
+    /// # Args
+    ///
+    /// * `node_degree` - The node's degree of separation from the center.
+    /// * `song_id` - The Genius ID of the node's song.
+    /// * `degree` - The maximum degree of separation for the graph.
+    ///
+    /// # Returns
+    ///
+    /// Whether the node should be expanded.
+    pub fn expands(&self, node_degree: u8, song_id: u32, degree: u8) -> bool {
+        node_degree < degree || (node_degree == degree && self.pin.contains(&song_id))
+    }
+
+    /// Determines the most nodes the graph may contain.
+    ///
+    /// # Args
+    ///
+    /// * `deployment_limit` - The deployment's node limit, if any.
+    ///
+    /// # Returns
+    ///
+    /// The lower of the requested and deployment limits, or `None` if neither is set.
+    pub fn node_limit(&self, deployment_limit: Option<usize>) -> Option<usize> {
+        self.max_nodes.into_iter().chain(deployment_limit).min()
+    }
+
+    /// Determines if a song was released close enough to the center song to be included.
+    /// Without a span, or if the center song's year is unknown, every song is included.
+    /// Otherwise songs with an unknown year are left out.
+    ///
+    /// # Args
+    ///
+    /// * `center_year` - The release year of the center song.
+    /// * `song` - The song to check.
+    ///
+    /// # Returns
+    ///
+    /// Whether the song is within the year span.
+    pub fn within_year_span(&self, center_year: Option<i32>, song: &SongData) -> bool {
+        match (self.year_span, center_year) {
+            (Some(span), Some(center_year)) => song
+                .release_year()
+                .map(|year| year.abs_diff(center_year) <= u32::from(span))
+                .unwrap_or(false),
+            _ => true,
+        }
+    }
+}
+
+/// Node data in a graph.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct GraphNode {
+    /// Degree of separation from the center.
+    pub degree: u8,
+    /// Genius song data.
+    pub song: SongData,
+    /// Where the node's data came from, if requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Genius IDs of the songs from the center that led to the node, if requested.
+    /// Starts with the center and excludes the node itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub via: Option<Vec<u32>>,
+    /// The album the song is on, if requested and the song is on one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<AlbumData>,
+}
+
+impl GraphNode {
+    /// Create a new graph node.
+    ///
+    /// # Args
+    ///
+    /// * `degree` - Degree of separation from the center.
+    /// * `song` - Genius song data.
+    ///
+    /// # Returns
+    ///
+    /// The graph node.
+    pub fn new(degree: u8, song: SongData) -> Self {
+        Self {
+            degree,
+            song,
+            provenance: None,
+            via: None,
+            album: None,
+        }
+    }
+}
+
+/// Edge data in a graph.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct EdgeData {
+    /// The type of relationship.
+    pub relationship_type: RelationshipType,
+    /// How many times the relationship was found between the same pair of songs.
+    pub weight: u32,
+}
+
+impl EdgeData {
+    /// Create edge data for a relationship found once.
+    ///
+    /// # Args
+    ///
+    /// * `relationship_type` - The type of relationship.
+    ///
+    /// # Returns
+    ///
+    /// The edge data.
+    pub fn new(relationship_type: RelationshipType) -> Self {
+        Self {
+            relationship_type,
+            weight: 1,
+        }
+    }
+}
+
+/// Aggregate metrics about a graph, treating its edges as undirected.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct GraphMetrics {
+    /// Number of nodes in the graph.
+    pub node_count: usize,
+    /// Number of edges in the graph.
+    pub edge_count: usize,
+    /// Number of connected components in the graph.
+    pub component_count: usize,
+    /// Longest shortest path between any two nodes.
+    /// `None` (infinite) if the graph is disconnected or empty.
+    pub diameter: Option<usize>,
+    /// Average shortest path length between all pairs of connected nodes.
+    /// `None` if no two nodes are connected.
+    pub average_path_length: Option<f64>,
+}
+
+/// Degree statistics of a graph, as counts of nodes and edges.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct GraphStats {
+    /// Number of nodes in the graph.
+    pub node_count: usize,
+    /// Number of edges in the graph.
+    pub edge_count: usize,
+    /// Most edges, in either direction, at any one node.
+    pub max_degree: usize,
+    /// Average number of outgoing edges per node.
+    /// `None` if the graph is empty.
+    pub average_out_degree: Option<f64>,
+    /// Number of nodes at each degree of separation from the center, starting from the center.
+    pub nodes_per_degree: Vec<usize>,
+}
+
+/// The community a song belongs to within a graph.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SongCommunity {
+    /// Genius ID of the song.
+    pub song_id: u32,
+    /// The community the song belongs to.
+    pub community_id: usize,
+}
+
+/// How many songs by an artist appear in a graph.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ArtistCount {
+    /// The artist's name.
+    pub artist: String,
+    /// Number of the artist's songs in the graph.
+    pub song_count: usize,
+}
+
+/// How central a song is within a graph.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct SongRank {
+    /// Genius ID of the song.
+    pub song_id: u32,
+    /// The song's PageRank score.
+    pub score: f64,
+}
+
+/// How many pairs of related songs in a graph have a relationship reported by Genius from both songs.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct Reciprocity {
+    /// Pairs of songs where a relationship is reported from both songs.
+    pub reciprocated: usize,
+    /// Pairs of songs where every relationship is only reported from one song.
+    pub one_way: usize,
+    /// Fraction of pairs of songs that are reciprocated.
+    /// `None` if there are no related pairs.
+    pub ratio: Option<f64>,
+}
+
+/// An edge between two songs in a graph.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct GraphEdge {
+    /// Genius ID of the source song.
+    pub source: u32,
+    /// Genius ID of the target song.
+    pub target: u32,
+    /// Relationship from the source song to the target song.
+    pub relationship_type: RelationshipType,
+    /// How the relationship was discovered, if recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery: Option<EdgeSource>,
+}
+
+/// How a relationship between two songs was discovered.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeSource {
+    /// Reported by Genius for the source song during this build, in its forward direction
+    /// (e.g. `samples`).
+    GeniusDirect,
+    /// Reported by Genius for the source song during this build, in its inverse direction
+    /// (e.g. `sampled_in`).
+    GeniusInverse,
+    /// Inferred as the inverse of a relationship found for the target song,
+    /// without Genius reporting it for the source song.
+    Inferred,
+    /// Reported by Genius for the source song and read from the cache.
+    Cached,
+}
+
+impl EdgeSource {
+    /// Returns how a relationship found for the source song of an edge was discovered.
+    ///
+    /// # Args
+    ///
+    /// * `provenance` - Where the source song's relationships came from.
+    /// * `relationship_type` - The relationship from the source song to the target song.
+    ///
+    /// # Returns
+    ///
+    /// How the edge was discovered.
+    pub fn found(provenance: Provenance, relationship_type: RelationshipType) -> Self {
+        match provenance {
+            Provenance::Cache => Self::Cached,
+            Provenance::Genius if relationship_type.family() == relationship_type => {
+                Self::GeniusDirect
+            }
+            Provenance::Genius => Self::GeniusInverse,
+        }
+    }
+}
+
+/// How long each phase of a request took, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+pub struct Timings {
+    /// Time spent reading from and writing to the cache.
+    pub cache_ms: f64,
+    /// Time spent waiting on Genius.
+    pub genius_ms: f64,
+    /// Time spent serializing the response.
+    pub serialize_ms: f64,
+    /// Time spent handling the whole request.
+    pub total_ms: f64,
+}
+
+/// A graph as lists of songs and edges between Genius IDs, for clients that
+/// would rather not resolve node indices.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct AdjacencyList {
+    /// Songs in the graph.
+    pub nodes: Vec<SongData>,
+    /// Relationships between the songs.
+    pub edges: Vec<AdjacencyEdge>,
+}
+
+/// A relationship between two songs in an adjacency list.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct AdjacencyEdge {
+    /// Genius ID of the source song.
+    pub source: u32,
+    /// Genius ID of the target song.
+    pub target: u32,
+    /// Relationship from the source song to the target song.
+    #[serde(rename = "type")]
+    pub relationship_type: RelationshipType,
+}
+
+/// A stored version of a graph, used to work out what changed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct GraphSnapshot {
+    /// Nodes in the graph.
+    pub nodes: Vec<GraphNode>,
+    /// Edges in the graph.
+    pub edges: Vec<GraphEdge>,
+}
+
+/// A single node or edge of a graph.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GraphElement {
+    /// A song node.
+    Node(Box<GraphNode>),
+    /// A relationship edge.
+    Edge(GraphEdge),
+}
+
+/// The changes between two versions of a graph.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct GraphDelta {
+    /// Nodes and edges only in the newer version.
+    pub added: Vec<GraphElement>,
+    /// Nodes and edges only in the older version.
+    pub removed: Vec<GraphElement>,
+}
+
+/// An estimate of a graph's size made only from cached data.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GraphEstimate {
+    /// Nodes reachable through cached relationships.
+    pub node_count: usize,
+    /// Edges reachable through cached relationships.
+    pub edge_count: usize,
+    /// Nodes that would need a fresh Genius fetch to expand.
+    /// Each may add further nodes not counted here.
+    pub uncached_count: usize,
+}
+
+/// A song whose data could not be loaded, while building a graph or warming the cache.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BuildError {
+    /// Genius ID of the song.
+    pub song_id: u32,
+    /// Why its data could not be loaded.
+    pub message: String,
+}
+
+/// The outcome of warming the cache for a set of songs.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct WarmSummary {
+    /// Number of songs whose data was fetched from Genius and cached.
+    pub cached: usize,
+    /// Number of songs whose data was already cached.
+    pub already_cached: usize,
+    /// Songs whose data could not be loaded.
+    pub failed: Vec<BuildError>,
+}
+
+/// A graph of song relationships and whether building it stopped early.
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct GraphNode {
-    /// Degree of separation from the center.
-    pub degree: u8,
-    /// Genius song data.
-    pub song: SongData,
+pub struct GraphBuild {
+    /// The graph built.
+    pub graph: DiGraph<GraphNode, EdgeData>,
+    /// Whether some songs were left unexpanded to stay within limits.
+    pub truncated: bool,
+    /// How each edge was discovered, in edge order.
+    pub edge_sources: Vec<EdgeSource>,
+    /// How many relationships of each type were left out as not relevant.
+    pub filtered_types: HashMap<RelationshipType, usize>,
+    /// Songs left unexpanded because their relationships failed to load, in a best-effort build.
+    pub errors: Vec<BuildError>,
 }
 
-impl GraphNode {
-    /// Create a new graph node.
-    ///
-    /// # Args
-    ///
-    /// * `degree` - Degree of separation from the center.
-    /// * `song` - Genius song data.
+/// Something found while building a graph, streamed to clients as it happens.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GraphEvent {
+    /// A song added to the graph.
+    Node(Box<GraphNode>),
+    /// A relationship added to the graph.
+    Edge {
+        /// Genius ID of the source song.
+        from: u32,
+        /// Genius ID of the target song.
+        to: u32,
+        /// Relationship from the source song to the target song.
+        relationship: RelationshipType,
+    },
+    /// The graph is finished.
+    Done {
+        /// Whether some songs were left unexpanded to stay within limits.
+        truncated: bool,
+    },
+    /// The graph could not be finished.
+    Error {
+        /// What went wrong.
+        message: String,
+    },
+}
+
+impl GraphEvent {
+    /// Name the kind of event, as used for the `kind` field.
     ///
     /// # Returns
     ///
-    /// The graph node.
-    pub fn new(degree: u8, song: SongData) -> Self {
-        Self { degree, song }
+    /// The name of the event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Node(_) => "node",
+            Self::Edge { .. } => "edge",
+            Self::Done { .. } => "done",
+            Self::Error { .. } => "error",
+        }
     }
 }
 
+/// A graph as it would appear when built to a given degree.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DegreeSnapshot {
+    /// The maximum degree of separation from the center.
+    pub degree: u8,
+    /// The graph at that degree.
+    pub graph: DiGraph<GraphNode, EdgeData>,
+}
+
 #[cfg(test)]
 mod tests {
     use genius_rust::{
+        album::Album,
         search::Hit,
         song::{Artist, Song, SongStatus},
     };
@@ -222,7 +1537,51 @@ mod tests {
     use super::*;
 
     #[fixture]
-    fn song() -> Song {
+    fn artist() -> Artist {
+        Artist {
+            api_path: "".into(),
+            header_image_url: "".into(),
+            id: 0,
+            image_url: "".into(),
+            index_character: None,
+            is_meme_verified: false,
+            is_verified: false,
+            name: "Barfoo".into(),
+            slug: None,
+            url: "".into(),
+            iq: None,
+        }
+    }
+
+    #[fixture]
+    fn album(artist: Artist) -> Album {
+        Album {
+            api_path: "".into(),
+            comment_count: None,
+            cover_art_thumbnail_url: None,
+            cover_art_url: "".into(),
+            custom_header_image_url: None,
+            full_title: "".into(),
+            header_image_url: None,
+            id: 54321,
+            lock_state: None,
+            name: "Foobar Deluxe".into(),
+            name_with_artist: None,
+            pyongs_count: None,
+            release_date: None,
+            release_date_components: None,
+            url: "".into(),
+            current_user_metadata: None,
+            album_pageviews: None,
+            artist,
+            cover_arts: None,
+            description_annotation: None,
+            song_performances: None,
+        }
+    }
+
+    #[fixture]
+    fn song(artist: Artist) -> Song {
         Song {
             annotation_count: 0,
             api_path: "".into(),
@@ -283,19 +1642,7 @@ mod tests {
             youtube_start: None,
             youtube_url: None,
             current_user_metadata: None,
-            primary_artist: Artist {
-                api_path: "".into(),
-                header_image_url: "".into(),
-                id: 0,
-                image_url: "".into(),
-                index_character: None,
-                is_meme_verified: false,
-                is_verified: false,
-                name: "Barfoo".into(),
-                slug: None,
-                url: "".into(),
-                iq: None,
-            },
+            primary_artist: artist,
             album: None,
             albums: None,
             custom_performances: None,
@@ -356,6 +1703,17 @@ mod tests {
         assert_eq!(to_value(&input).unwrap(), json!(expected));
     }
 
+    #[rstest]
+    #[case(RelationshipType::Samples)]
+    #[case(RelationshipType::InterpolatedBy)]
+    #[case(RelationshipType::PerformedLiveAs)]
+    #[case(RelationshipType::Translations)]
+    #[case(RelationshipType::Unknown)]
+    fn test_relationship_type_as_str(#[case] input: RelationshipType) {
+        assert_eq!(json!(input.as_str()), to_value(input).unwrap());
+        assert_eq!(RelationshipType::from(input.as_str()), input);
+    }
+
     #[rstest]
     #[case("samples", RelationshipType::Samples)]
     #[case("sampled_in", RelationshipType::SampledIn)]
@@ -392,7 +1750,317 @@ mod tests {
     #[case(false, RelationshipType::Translations)]
     #[case(false, RelationshipType::Unknown)]
     fn test_relationship_type_is_relevant(#[case] expected: bool, #[case] input: RelationshipType) {
-        assert_eq!(input.is_relevant(), expected);
+        assert_eq!(
+            input.is_relevant(&RelationshipType::default_relevant()),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("samples,sampled_in", Ok(HashSet::from([RelationshipType::Samples, RelationshipType::SampledIn])))]
+    #[case(" cover_of , ,covered_by ", Ok(HashSet::from([RelationshipType::CoverOf, RelationshipType::CoveredBy])))]
+    #[case("", Ok(HashSet::new()))]
+    #[case("samples,foobar", Err("Unknown relationship type: foobar".to_string()))]
+    fn test_relationship_type_parse_list(
+        #[case] input: &str,
+        #[case] expected: Result<HashSet<RelationshipType>, String>,
+    ) {
+        assert_eq!(RelationshipType::parse_list(input), expected);
+    }
+
+    #[rstest]
+    #[case("sampled_from=samples", Ok(HashMap::from([("sampled_from".to_string(), RelationshipType::Samples)])))]
+    #[case(" a = cover_of , ,b=covered_by ", Ok(HashMap::from([("a".to_string(), RelationshipType::CoverOf), ("b".to_string(), RelationshipType::CoveredBy)])))]
+    #[case("", Ok(HashMap::new()))]
+    #[case("sampled_from", Err("Invalid relationship alias: sampled_from".to_string()))]
+    #[case("sampled_from=foobar", Err("Unknown relationship type: foobar".to_string()))]
+    fn test_relationship_type_parse_aliases(
+        #[case] input: &str,
+        #[case] expected: Result<HashMap<String, RelationshipType>, String>,
+    ) {
+        assert_eq!(RelationshipType::parse_aliases(input), expected);
+    }
+
+    #[rstest]
+    #[case("samples", RelationshipType::Samples)]
+    #[case("lyrical_reference_of", RelationshipType::Unknown)]
+    fn test_relationship_raw_type(
+        #[case] raw: &str,
+        #[case] expected: RelationshipType,
+        song: Song,
+    ) {
+        let relationship =
+            Relationship::new(RelationshipType::from(raw), SongData::from(song)).with_raw_type(raw);
+        assert_eq!(relationship.relationship_type, expected);
+        let value = to_value(&relationship).unwrap();
+        assert_eq!(value["raw_type"], json!(raw));
+        assert_eq!(
+            from_value::<Relationship>(value)
+                .unwrap()
+                .raw_type
+                .as_deref(),
+            Some(raw)
+        );
+    }
+
+    #[rstest]
+    fn test_relationship_raw_type_missing(song: Song) {
+        let relationship = Relationship::new(RelationshipType::Samples, SongData::from(song));
+        let value = to_value(&relationship).unwrap();
+        assert!(value.get("raw_type").is_none());
+        assert_eq!(from_value::<Relationship>(value).unwrap(), relationship);
+    }
+
+    #[rstest]
+    fn test_song_data_split_stats() {
+        let song = SongData {
+            pageviews: Some(100),
+            ..SongData::new(1, "Foobar".into(), "The Sillys".into())
+        };
+        let (core, stats) = song.clone().split_stats();
+        assert_eq!(core.pageviews, None);
+        assert_eq!(stats.pageviews, Some(100));
+        assert_eq!(core.with_stats(stats), song);
+    }
+
+    #[rstest]
+    #[case(&["id", "title"], json!({"id": 1, "title": "Foobar"}))]
+    #[case(&["id", "foobar"], json!({"id": 1}))]
+    #[case(&["pageviews"], json!({}))]
+    fn test_song_data_retain_fields(#[case] fields: &[&str], #[case] expected: Value) {
+        let mut song = to_value(SongData::new(1, "Foobar".into(), "The Sillys".into())).unwrap();
+        let fields = fields.iter().map(|f| f.to_string()).collect();
+        SongData::retain_fields(&mut song, &fields);
+        assert_eq!(song, expected);
+    }
+
+    #[rstest]
+    fn test_relationship_from_id(song: Song) {
+        let relationship = Relationship::new(RelationshipType::Samples, SongData::from(song));
+        assert!(to_value(&relationship).unwrap().get("from_id").is_none());
+        let relationship = relationship.with_from_id(12345);
+        let value = to_value(&relationship).unwrap();
+        assert_eq!(value["from_id"], json!(12345));
+        assert_eq!(from_value::<Relationship>(value).unwrap(), relationship);
+    }
+
+    #[rstest]
+    #[case("json", Ok(GraphFormat::Json))]
+    #[case(" csv ", Ok(GraphFormat::Csv))]
+    #[case("html", Ok(GraphFormat::Html))]
+    #[case("turtle", Ok(GraphFormat::Turtle))]
+    #[case("dot", Ok(GraphFormat::Dot))]
+    #[case("graphml", Ok(GraphFormat::GraphMl))]
+    #[case("adjacency", Ok(GraphFormat::Adjacency))]
+    #[case("foobar", Err("Unknown graph format: foobar".to_string()))]
+    fn test_graph_format_parse(#[case] input: &str, #[case] expected: Result<GraphFormat, String>) {
+        assert_eq!(GraphFormat::parse(input), expected);
+    }
+
+    #[rstest]
+    #[case("text/csv", Some(GraphFormat::Csv))]
+    #[case("application/json; charset=utf-8", Some(GraphFormat::Json))]
+    #[case("text/vnd.graphviz;q=0.5, text/turtle", Some(GraphFormat::Turtle))]
+    #[case("application/xml;q=0.9, text/html;q=0.1", Some(GraphFormat::GraphMl))]
+    #[case("text/csv;q=0, */*", None)]
+    #[case("image/png", None)]
+    fn test_graph_format_from_accept(#[case] input: &str, #[case] expected: Option<GraphFormat>) {
+        assert_eq!(GraphFormat::from_accept(input), expected);
+    }
+
+    #[rstest]
+    #[case("relevance", SearchSort::Relevance)]
+    #[case("title", SearchSort::Title)]
+    #[case("artist", SearchSort::Artist)]
+    #[case("foobar", SearchSort::Relevance)]
+    fn test_search_sort_from_str(#[case] input: &str, #[case] expected: SearchSort) {
+        assert_eq!(SearchSort::from(input), expected);
+    }
+
+    #[rstest]
+    fn test_lookup_serialize() {
+        assert_eq!(
+            to_value(Lookup::<SongData>::new(None)).unwrap(),
+            json!({ "found": false })
+        );
+        assert_eq!(
+            to_value(Lookup::new(Some(1))).unwrap(),
+            json!({ "found": true, "data": 1 })
+        );
+    }
+
+    #[rstest]
+    #[case(RelationshipType::Samples, Some(0.2))]
+    #[case(RelationshipType::CoveredBy, Some(0.5))]
+    #[case(RelationshipType::Translations, Some(1.0))]
+    #[case(RelationshipType::Unknown, None)]
+    fn test_relationship_type_distance(
+        #[case] input: RelationshipType,
+        #[case] expected: Option<f64>,
+    ) {
+        assert_eq!(input.distance(), expected);
+    }
+
+    #[rstest]
+    #[case(0.0, Some(0.0))]
+    #[case(1.5, Some(1.5))]
+    #[case(-1.0, None)]
+    #[case(f64::NAN, None)]
+    #[case(f64::INFINITY, None)]
+    fn test_path_distance_new(#[case] input: f64, #[case] expected: Option<f64>) {
+        assert_eq!(PathDistance::new(input).map(|d| d.value()), expected);
+    }
+
+    #[rstest]
+    fn test_path_distance_negative_zero() {
+        let negative = PathDistance::new(-0.0).unwrap();
+        let positive = PathDistance::new(0.0).unwrap();
+        assert_eq!(negative, positive);
+        assert_eq!(negative.value().to_bits(), positive.value().to_bits());
+    }
+
+    #[rstest]
+    fn test_lookup_with_cached_at() {
+        assert_eq!(
+            to_value(Lookup::new(Some(1)).with_cached_at(Some(900), 1000)).unwrap(),
+            json!({ "found": true, "data": 1, "cached_at": 900, "age_seconds": 100 })
+        );
+        assert_eq!(
+            to_value(Lookup::new(Some(1)).with_cached_at(None, 1000)).unwrap(),
+            json!({ "found": true, "data": 1 })
+        );
+    }
+
+    #[rstest]
+    #[case(json!({ "at": 900, "data": [1, 2] }), Some(900))]
+    #[case(json!({ "at": null, "data": [1, 2] }), None)]
+    #[case(json!([1, 2]), None)]
+    fn test_cached_deserialize(#[case] input: Value, #[case] expected: Option<u64>) {
+        let cached: Cached<Vec<u32>> = from_value(input).unwrap();
+        assert_eq!(cached.at, expected);
+        assert_eq!(cached.data, vec![1, 2]);
+    }
+
+    #[rstest]
+    fn test_cached_legacy_song() {
+        let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+        let cached: Cached<SongData> = from_value(to_value(&song).unwrap()).unwrap();
+        assert_eq!(
+            cached,
+            Cached {
+                at: None,
+                data: song
+            }
+        );
+    }
+
+    #[rstest]
+    fn test_cached_optional_song() {
+        let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+        let cached: Cached<Option<SongData>> = from_value(to_value(&song).unwrap()).unwrap();
+        assert_eq!(cached.data, Some(song));
+        let missing: Cached<Option<SongData>> = from_value(json!({"at": 5, "data": null})).unwrap();
+        assert_eq!(
+            missing,
+            Cached {
+                at: Some(5),
+                data: None
+            }
+        );
+    }
+
+    #[rstest]
+    #[case("cache", Provenance::Cache)]
+    #[case("genius", Provenance::Genius)]
+    fn test_provenance_serialize(#[case] expected: &str, #[case] input: Provenance) {
+        assert_eq!(to_value(input).unwrap(), json!(expected));
+    }
+
+    #[rstest]
+    #[case(RelationshipType::Samples, RelationshipType::SampledIn)]
+    #[case(RelationshipType::Interpolates, RelationshipType::InterpolatedBy)]
+    #[case(RelationshipType::CoverOf, RelationshipType::CoveredBy)]
+    #[case(RelationshipType::RemixOf, RelationshipType::RemixedBy)]
+    #[case(RelationshipType::LiveVersionOf, RelationshipType::PerformedLiveAs)]
+    #[case(RelationshipType::TranslationOf, RelationshipType::Translations)]
+    #[case(RelationshipType::Unknown, RelationshipType::Unknown)]
+    fn test_relationship_type_inverse(
+        #[case] input: RelationshipType,
+        #[case] expected: RelationshipType,
+    ) {
+        assert_eq!(input.inverse(), expected);
+        assert_eq!(expected.inverse(), input);
+    }
+
+    #[rstest]
+    #[case(RelationshipType::Samples, RelationshipType::Samples)]
+    #[case(RelationshipType::SampledIn, RelationshipType::Samples)]
+    #[case(RelationshipType::Interpolates, RelationshipType::Interpolates)]
+    #[case(RelationshipType::InterpolatedBy, RelationshipType::Interpolates)]
+    #[case(RelationshipType::CoverOf, RelationshipType::CoverOf)]
+    #[case(RelationshipType::CoveredBy, RelationshipType::CoverOf)]
+    #[case(RelationshipType::RemixOf, RelationshipType::RemixOf)]
+    #[case(RelationshipType::RemixedBy, RelationshipType::RemixOf)]
+    #[case(RelationshipType::LiveVersionOf, RelationshipType::LiveVersionOf)]
+    #[case(RelationshipType::PerformedLiveAs, RelationshipType::LiveVersionOf)]
+    #[case(RelationshipType::TranslationOf, RelationshipType::TranslationOf)]
+    #[case(RelationshipType::Translations, RelationshipType::TranslationOf)]
+    #[case(RelationshipType::Unknown, RelationshipType::Unknown)]
+    fn test_relationship_type_family(
+        #[case] input: RelationshipType,
+        #[case] expected: RelationshipType,
+    ) {
+        assert_eq!(input.family(), expected);
+    }
+
+    #[rstest]
+    #[case(RelationshipType::Samples, 5)]
+    #[case(RelationshipType::SampledIn, 5)]
+    #[case(RelationshipType::InterpolatedBy, 4)]
+    #[case(RelationshipType::RemixedBy, 3)]
+    #[case(RelationshipType::CoverOf, 2)]
+    #[case(RelationshipType::PerformedLiveAs, 1)]
+    #[case(RelationshipType::Translations, 1)]
+    #[case(RelationshipType::Unknown, 0)]
+    fn test_relationship_type_weight(#[case] input: RelationshipType, #[case] expected: u32) {
+        assert_eq!(input.weight(), expected);
+    }
+
+    #[rstest]
+    fn test_relationship_type_legend() {
+        let legend = RelationshipType::legend();
+        assert_eq!(legend.len(), 13);
+        assert_eq!(legend[&0], "samples");
+        for (code, name) in legend {
+            let relationship_type = RelationshipType::from_code(code).unwrap();
+            assert_eq!(relationship_type.as_str(), name);
+            assert_eq!(relationship_type.code(), code);
+        }
+        assert_eq!(RelationshipType::from_code(13), None);
+    }
+
+    #[rstest]
+    #[case(RelationshipOrder::Strength, &[3, 1, 2, 4])]
+    #[case(RelationshipOrder::Insertion, &[4, 1, 3, 2])]
+    fn test_relationship_order_apply(#[case] order: RelationshipOrder, #[case] expected: &[u32]) {
+        let song = |id| SongData::new(id, "Foobar".into(), "Barfoo".into());
+        let mut relationships = vec![
+            Relationship::new(RelationshipType::CoverOf, song(4)),
+            Relationship::new(RelationshipType::Interpolates, song(1)),
+            Relationship::new(RelationshipType::SampledIn, song(3)),
+            Relationship::new(RelationshipType::InterpolatedBy, song(2)),
+        ];
+        order.apply(&mut relationships);
+        let result = relationships.iter().map(|r| r.song.id).collect::<Vec<_>>();
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case("strength", RelationshipOrder::Strength)]
+    #[case("insertion", RelationshipOrder::Insertion)]
+    #[case("foobar", RelationshipOrder::Strength)]
+    fn test_relationship_order_from_str(#[case] input: &str, #[case] expected: RelationshipOrder) {
+        assert_eq!(RelationshipOrder::from(input), expected);
     }
 
     #[rstest]
@@ -405,6 +2073,22 @@ mod tests {
         assert_eq!(result.id, id);
         assert_eq!(result.title, title);
         assert_eq!(result.artist_name, artist_name);
+        assert_eq!(result.pageviews, None);
+    }
+
+    #[rstest]
+    fn test_song_data_id_eq() {
+        let song = SongData::new(12345, "Foobar".into(), "Barfoo".into());
+        let with_metadata = SongData {
+            pageviews: Some(987654),
+            album: Some("Foobar Deluxe".into()),
+            ..song.clone()
+        };
+        let other_song = SongData::new(54321, "Foobar".into(), "Barfoo".into());
+        assert!(song.id_eq(&with_metadata));
+        assert_ne!(song, with_metadata);
+        assert!(!song.id_eq(&other_song));
+        assert_ne!(song, other_song);
     }
 
     #[rstest]
@@ -413,14 +2097,200 @@ mod tests {
         assert_eq!(result.id, 12345);
         assert_eq!(result.title, "Foobar");
         assert_eq!(result.artist_name, "Barfoo");
+        assert_eq!(result.pageviews, None);
+        assert_eq!(result.album, None);
+    }
+
+    #[rstest]
+    fn test_artist_data_from_artist(mut artist: Artist) {
+        artist.id = 678;
+        let result = ArtistData::from(artist);
+        assert_eq!(result.id, 678);
+        assert_eq!(result.name, "Barfoo");
+    }
+
+    #[rstest]
+    #[case(7, Some(vec![1, 3]))]
+    #[case(8, Some(vec![2]))]
+    #[case(9, None)]
+    fn test_artist_songs_from_listed(#[case] artist_id: u32, #[case] expected: Option<Vec<u32>>) {
+        let body = json!({"response": {"songs": [
+            {"id": 1, "title_with_featured": "Foo", "primary_artist": {"id": 7, "name": "The Sillys"}},
+            {"id": 2, "title_with_featured": "Bar (Ft. The Sillys)", "primary_artist": {"id": 8, "name": "The Seriouses"}},
+            {
+                "id": 3,
+                "title_with_featured": "Baz",
+                "primary_artist": {"id": 7, "name": "The Sillys", "url": "https://genius.com"},
+                "song_art_image_thumbnail_url": "https://images.genius.com/3.png"
+            },
+        ]}});
+        let listed = from_value::<GeniusResponse<ListedSongs>>(body)
+            .unwrap()
+            .response
+            .songs;
+        let result = ArtistSongs::from_listed(artist_id, listed);
+        assert_eq!(
+            result
+                .as_ref()
+                .map(|found| found.songs.iter().map(|song| song.id).collect::<Vec<_>>()),
+            expected
+        );
+        if let Some(found) = result {
+            assert_eq!(found.artist.id, artist_id);
+        }
+    }
+
+    #[rstest]
+    fn test_song_data_from_song_album(mut song: Song, album: Album) {
+        song.album = Some(album);
+        let result = SongData::from(song);
+        assert_eq!(result.album, Some("Foobar Deluxe".into()));
+    }
+
+    #[rstest]
+    #[case("夜に駆ける (Yoru ni Kakeru)", Some("夜に駆ける"))]
+    #[case("Foobar (Remix)", None)]
+    #[case("夜に駆ける", None)]
+    #[case("夜に駆ける ()", None)]
+    fn test_song_data_from_song_original_title(
+        mut song: Song,
+        #[case] title: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        song.title = title.into();
+        song.title_with_featured = title.into();
+        let result = SongData::from(song);
+        assert_eq!(result.title, title);
+        assert_eq!(result.original_title.as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case("夜に駆ける (Yoru ni Kakeru)", Some("Yoru ni Kakeru"))]
+    #[case("Дорогой длинною (Dorogoi dlinnoyu)", Some("Dorogoi dlinnoyu"))]
+    #[case("Foobar (Remix)", None)]
+    #[case("夜に駆ける", None)]
+    fn test_song_data_from_song_romanized_title(
+        mut song: Song,
+        #[case] title: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        song.title = title.into();
+        song.title_with_featured = title.into();
+        let result = SongData::from(song);
+        assert_eq!(result.romanized_title.as_deref(), expected);
+    }
+
+    #[rstest]
+    fn test_song_data_from_hit_original_title(mut hit: Hit) {
+        hit.result.title = "夜に駆ける (Yoru ni Kakeru)".into();
+        let result = SongData::from(hit);
+        assert_eq!(result.original_title, None);
+        assert_eq!(result.romanized_title, None);
+    }
+
+    #[rstest]
+    #[case(
+        "https://images.genius.com/art.300x300x1.jpg",
+        "https://images.genius.com/artist.jpg",
+        Some("https://images.genius.com/art.300x300x1.jpg")
+    )]
+    #[case(
+        "",
+        "https://images.genius.com/artist.jpg",
+        Some("https://images.genius.com/artist.jpg")
+    )]
+    #[case(
+        "https://assets.genius.com/images/default_cover_image.png?1",
+        "https://images.genius.com/artist.jpg",
+        Some("https://images.genius.com/artist.jpg")
+    )]
+    #[case("", "", Some("https://images.genius.com/header.jpg"))]
+    fn test_song_data_from_song_thumbnail_url(
+        mut song: Song,
+        #[case] song_art: &str,
+        #[case] artist_image: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        song.song_art_image_thumbnail_url = song_art.into();
+        song.primary_artist.image_url = artist_image.into();
+        song.primary_artist.header_image_url = "https://images.genius.com/header.jpg".into();
+        let result = SongData::from(song);
+        assert_eq!(result.thumbnail_url.as_deref(), expected);
+    }
+
+    #[rstest]
+    fn test_song_data_from_hit_thumbnail_url(mut hit: Hit) {
+        hit.result.primary_artist.image_url = "https://images.genius.com/artist.jpg".into();
+        let result = SongData::from(hit);
+        assert_eq!(
+            result.thumbnail_url.as_deref(),
+            Some("https://images.genius.com/artist.jpg")
+        );
+    }
+
+    #[rstest]
+    fn test_song_data_from_song_release_date(mut song: Song) {
+        song.release_date = Some("1971-03-05".into());
+        let result = SongData::from(song);
+        assert_eq!(result.release_date.as_deref(), Some("1971-03-05"));
+    }
+
+    #[rstest]
+    #[case(Some("1971-03-05"), Some(1971))]
+    #[case(Some("1971"), Some(1971))]
+    #[case(Some("March 5, 1971"), Some(1971))]
+    #[case(Some("unknown"), None)]
+    #[case(None, None)]
+    fn test_song_data_release_year(
+        #[case] release_date: Option<&str>,
+        #[case] expected: Option<i32>,
+    ) {
+        let song = SongData {
+            release_date: release_date.map(String::from),
+            ..SongData::new(1, "Foobar".into(), "The Sillys".into())
+        };
+        assert_eq!(song.release_year(), expected);
+    }
+
+    #[rstest]
+    fn test_song_data_from_song_pageviews(mut song: Song) {
+        song.stats.pageviews = Some(987654);
+        let result = SongData::from(song);
+        assert_eq!(result.pageviews, Some(987654));
+    }
+
+    #[rstest]
+    fn test_album_data_from_album(song: Song, album: Album) {
+        let result = AlbumData::from(album);
+        assert_eq!(result.id, 54321);
+        assert_eq!(result.name, "Foobar Deluxe");
+        assert_eq!(result.artist, ArtistData::from(song.primary_artist));
     }
 
     #[rstest]
-    fn test_song_data_from_hit(hit: Hit) {
+    fn test_song_data_from_hit(mut hit: Hit, album: Album) {
+        hit.result.stats.pageviews = Some(987654);
+        hit.result.album = Some(album);
         let result = SongData::from(hit);
         assert_eq!(result.id, 12345);
         assert_eq!(result.title, "Foobar");
         assert_eq!(result.artist_name, "Barfoo");
+        assert_eq!(result.pageviews, None);
+        assert_eq!(result.album, None);
+    }
+
+    #[rstest]
+    fn test_song_data_deserialize_without_metadata() {
+        let result = from_value::<SongData>(json!({
+            "id": 12345,
+            "title": "Foobar",
+            "artist_name": "Barfoo",
+        }))
+        .unwrap();
+        assert_eq!(
+            result,
+            SongData::new(12345, "Foobar".into(), "Barfoo".into())
+        );
     }
 
     #[rstest]
@@ -441,6 +2311,38 @@ mod tests {
         assert_eq!(result.song, song);
     }
 
+    #[rstest]
+    fn test_relationship_dedup() {
+        let song = |id| SongData::new(id, "Foobar".into(), "Barfoo".into());
+        let relationships = vec![
+            Relationship::new(RelationshipType::Samples, song(1)).with_raw_type("samples"),
+            Relationship::new(RelationshipType::Samples, song(2)),
+            Relationship::new(RelationshipType::Samples, song(1)),
+            Relationship::new(RelationshipType::Interpolates, song(1)),
+            Relationship::new(RelationshipType::Samples, song(2)),
+        ];
+        let result = Relationship::dedup(relationships.clone());
+        assert_eq!(
+            result,
+            vec![
+                Relationship {
+                    listed: Some(2),
+                    ..relationships[0].clone()
+                },
+                Relationship {
+                    listed: Some(2),
+                    ..relationships[1].clone()
+                },
+                relationships[3].clone()
+            ]
+        );
+        let listings = result
+            .iter()
+            .map(Relationship::listings)
+            .collect::<Vec<_>>();
+        assert_eq!(listings, [2, 2, 1]);
+    }
+
     #[rstest]
     fn test_queue_item_new() {
         let result = QueueItem::new(255, 12345, NodeIndex::default());
@@ -449,6 +2351,45 @@ mod tests {
         assert_eq!(result.index, NodeIndex::default());
     }
 
+    #[rstest]
+    #[case(0, 1, 2, true)]
+    #[case(1, 1, 2, true)]
+    #[case(2, 1, 2, true)]
+    #[case(2, 2, 2, false)]
+    #[case(3, 1, 2, false)]
+    #[case(0, 1, 0, true)]
+    #[case(0, 2, 0, false)]
+    fn test_graph_options_expands(
+        #[case] node_degree: u8,
+        #[case] song_id: u32,
+        #[case] degree: u8,
+        #[case] expected: bool,
+    ) {
+        let options = GraphOptions {
+            pin: BTreeSet::from([1]),
+            ..GraphOptions::default()
+        };
+        assert_eq!(options.expands(node_degree, song_id, degree), expected);
+    }
+
+    #[rstest]
+    #[case(None, None, None)]
+    #[case(Some(5), None, Some(5))]
+    #[case(None, Some(3), Some(3))]
+    #[case(Some(5), Some(3), Some(3))]
+    #[case(Some(2), Some(3), Some(2))]
+    fn test_graph_options_node_limit(
+        #[case] max_nodes: Option<usize>,
+        #[case] deployment_limit: Option<usize>,
+        #[case] expected: Option<usize>,
+    ) {
+        let options = GraphOptions {
+            max_nodes,
+            ..GraphOptions::default()
+        };
+        assert_eq!(options.node_limit(deployment_limit), expected);
+    }
+
     #[rstest]
     fn test_graph_node_new() {
         let result = GraphNode::new(255, SongData::new(12345, "Foobar".into(), "Barfoo".into()));
@@ -457,5 +2398,83 @@ mod tests {
             result.song,
             SongData::new(12345, "Foobar".into(), "Barfoo".into())
         );
+        assert_eq!(result.provenance, None);
+    }
+
+    #[rstest]
+    fn test_edge_data_serialization() {
+        let result = to_value(EdgeData::new(RelationshipType::Samples)).unwrap();
+        assert_eq!(
+            result,
+            json!({ "relationship_type": "samples", "weight": 1 })
+        );
+    }
+
+    #[rstest]
+    fn test_graph_element_serialize() {
+        let edge = GraphEdge {
+            source: 1,
+            target: 2,
+            relationship_type: RelationshipType::Samples,
+            discovery: None,
+        };
+        assert_eq!(
+            to_value(GraphElement::Edge(edge)).unwrap(),
+            json!({"kind": "edge", "source": 1, "target": 2, "relationship_type": "samples"})
+        );
+        let edge = GraphEdge {
+            discovery: Some(EdgeSource::GeniusDirect),
+            ..edge
+        };
+        assert_eq!(to_value(edge).unwrap()["discovery"], json!("genius_direct"));
+    }
+
+    #[rstest]
+    #[case(
+        Provenance::Genius,
+        RelationshipType::Samples,
+        EdgeSource::GeniusDirect
+    )]
+    #[case(
+        Provenance::Genius,
+        RelationshipType::SampledIn,
+        EdgeSource::GeniusInverse
+    )]
+    #[case(Provenance::Cache, RelationshipType::Samples, EdgeSource::Cached)]
+    #[case(Provenance::Cache, RelationshipType::SampledIn, EdgeSource::Cached)]
+    fn test_edge_source_found(
+        #[case] provenance: Provenance,
+        #[case] relationship_type: RelationshipType,
+        #[case] expected: EdgeSource,
+    ) {
+        assert_eq!(EdgeSource::found(provenance, relationship_type), expected);
+    }
+
+    #[rstest]
+    fn test_graph_event_serialize() {
+        let node = GraphNode::new(0, SongData::new(1, "Foo".into(), "Bar".into()));
+        let event = GraphEvent::Node(Box::new(node.clone()));
+        let mut expected = to_value(&node).unwrap();
+        expected["kind"] = json!("node");
+        assert_eq!(to_value(&event).unwrap(), expected);
+        assert_eq!(event.name(), "node");
+
+        let event = GraphEvent::Edge {
+            from: 1,
+            to: 2,
+            relationship: RelationshipType::Samples,
+        };
+        assert_eq!(
+            to_value(&event).unwrap(),
+            json!({"kind": "edge", "from": 1, "to": 2, "relationship": "samples"})
+        );
+        assert_eq!(event.name(), "edge");
+
+        let event = GraphEvent::Done { truncated: false };
+        assert_eq!(
+            to_value(&event).unwrap(),
+            json!({"kind": "done", "truncated": false})
+        );
+        assert_eq!(event.name(), "done");
     }
 }
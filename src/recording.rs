@@ -0,0 +1,252 @@
+//! Recording the Genius responses behind a request, and replaying them for debugging.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    future::Future,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{from_slice, to_vec_pretty};
+
+use crate::{AlbumData, Relationship, SongData, StateError};
+
+tokio::task_local! {
+    /// Recorder of the request being handled by the current task, if it asked to be recorded.
+    static RECORDER: Arc<Recorder>;
+}
+
+/// Genius responses received while handling a request.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct Recording {
+    /// Songs by Genius ID.
+    pub songs: BTreeMap<u32, SongData>,
+    /// Relationships by the Genius ID of the song they are from.
+    pub relationships: BTreeMap<u32, Vec<Relationship>>,
+    /// Search results by query.
+    pub searches: BTreeMap<String, Vec<SongData>>,
+    /// Albums by the Genius ID of a song on them, or `None` if the song is on no album.
+    #[serde(default)]
+    pub albums: BTreeMap<u32, Option<AlbumData>>,
+}
+
+impl Recording {
+    /// Load a recording from a file.
+    ///
+    /// # Args
+    ///
+    /// * `path` - The recording file.
+    ///
+    /// # Returns
+    ///
+    /// The recording.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        Ok(from_slice(&fs::read(path)?)?)
+    }
+
+    /// Save a recording to a file.
+    ///
+    /// # Args
+    ///
+    /// * `path` - The recording file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        fs::write(path, to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Replay a recorded song.
+    ///
+    /// # Args
+    ///
+    /// * `id` - The Genius ID of the song.
+    ///
+    /// # Returns
+    ///
+    /// The song data, or an error if the song was not recorded.
+    pub fn song(&self, id: u32) -> Result<SongData, StateError> {
+        self.songs
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| StateError::NotRecorded(format!("song {}", id)))
+    }
+
+    /// Replay a song's recorded relationships.
+    ///
+    /// # Args
+    ///
+    /// * `id` - The Genius ID of the song.
+    ///
+    /// # Returns
+    ///
+    /// The relationships, or an error if they were not recorded.
+    pub fn relationships(&self, id: u32) -> Result<Vec<Relationship>, StateError> {
+        self.relationships
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| StateError::NotRecorded(format!("relationships of song {}", id)))
+    }
+
+    /// Replay a song's recorded album.
+    ///
+    /// # Args
+    ///
+    /// * `id` - The Genius ID of the song.
+    ///
+    /// # Returns
+    ///
+    /// The album data, `None` if the song is on no album,
+    /// or an error if the album was not recorded.
+    pub fn album(&self, id: u32) -> Result<Option<AlbumData>, StateError> {
+        self.albums
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| StateError::NotRecorded(format!("album of song {}", id)))
+    }
+
+    /// Replay recorded search results.
+    ///
+    /// # Args
+    ///
+    /// * `query` - The search query.
+    ///
+    /// # Returns
+    ///
+    /// The search results, or an error if the search was not recorded.
+    pub fn search(&self, query: &str) -> Result<Vec<SongData>, StateError> {
+        self.searches
+            .get(query)
+            .cloned()
+            .ok_or_else(|| StateError::NotRecorded(format!("search for {}", query)))
+    }
+}
+
+/// Collects the Genius responses received while handling a single request.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    /// The responses received so far.
+    recording: Mutex<Recording>,
+}
+
+impl Recorder {
+    /// Run a future, recording the Genius responses it receives.
+    /// Responses received on other tasks are not recorded.
+    ///
+    /// # Args
+    ///
+    /// * `future` - The request's work.
+    ///
+    /// # Returns
+    ///
+    /// The output of the future.
+    pub async fn scope<F: Future>(self: Arc<Self>, future: F) -> F::Output {
+        RECORDER.scope(self, future).await
+    }
+
+    /// Return the responses recorded so far.
+    ///
+    /// # Returns
+    ///
+    /// The recording.
+    pub fn recording(&self) -> Recording {
+        self.recording
+            .lock()
+            .map(|recording| recording.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Determines if the current request is being recorded.
+///
+/// # Returns
+///
+/// Whether the current task is within [`Recorder::scope`].
+pub fn is_recording() -> bool {
+    RECORDER.try_with(|_| ()).is_ok()
+}
+
+/// Add a Genius response to the current request's recording.
+/// Does nothing outside of [`Recorder::scope`].
+///
+/// # Args
+///
+/// * `update` - Adds the response to the recording.
+pub fn capture(update: impl FnOnce(&mut Recording)) {
+    let _ = RECORDER.try_with(|recorder| {
+        if let Ok(mut recording) = recorder.recording.lock() {
+            update(&mut recording);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::RelationshipType;
+
+    #[fixture]
+    fn recording() -> Recording {
+        let song_1 = SongData::new(1, "Foobar".into(), "The Sillys".into());
+        let song_2 = SongData::new(2, "Barfoo".into(), "The Seriouses".into());
+        Recording {
+            songs: BTreeMap::from([(1, song_1.clone())]),
+            relationships: BTreeMap::from([(
+                1,
+                vec![Relationship::new(RelationshipType::Samples, song_2)],
+            )]),
+            searches: BTreeMap::from([("foobar".into(), vec![song_1])]),
+            albums: BTreeMap::from([(1, None)]),
+        }
+    }
+
+    #[rstest]
+    fn test_recording_round_trip(recording: Recording) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("recording.json");
+        recording.save(&path).unwrap();
+        assert_eq!(Recording::load(&path).unwrap(), recording);
+    }
+
+    #[rstest]
+    fn test_recording_replay(recording: Recording) {
+        assert_eq!(recording.song(1).unwrap().title, "Foobar");
+        assert_eq!(recording.relationships(1).unwrap().len(), 1);
+        assert_eq!(recording.search("foobar").unwrap().len(), 1);
+        assert_eq!(recording.album(1).unwrap(), None);
+        assert!(matches!(
+            recording.song(2),
+            Err(StateError::NotRecorded(what)) if what == "song 2"
+        ));
+        assert!(recording.relationships(2).is_err());
+        assert!(recording.search("barfoo").is_err());
+        assert!(recording.album(2).is_err());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_recorder_scope() {
+        let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+        let recorder = Arc::new(Recorder::default());
+        recorder
+            .clone()
+            .scope(async {
+                assert!(is_recording());
+                capture(|recording| {
+                    recording.songs.insert(1, song.clone());
+                });
+            })
+            .await;
+        assert!(!is_recording());
+        capture(|recording| {
+            recording.songs.insert(2, song.clone());
+        });
+        assert_eq!(
+            recorder.recording().songs,
+            BTreeMap::from([(1, song.clone())])
+        );
+    }
+}
@@ -1,20 +1,190 @@
 //! Functions for API routes.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::Infallible,
+    io::Error as IoError,
+    ops::Deref,
+    sync::{atomic::Ordering, Arc},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use axum::{
+    body::StreamBody,
+    error_handling::HandleErrorLayer,
     extract::{Path, Query, State as AxumState},
-    response::Json,
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{delete, get, post, put},
+    BoxError, Router,
 };
-use http::StatusCode;
-use redis::ConnectionLike;
+use futures::{stream, Stream, StreamExt};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use redis::cmd;
 use semver::Version;
 use serde_json::{json, Value};
+use subtle::ConstantTimeEq;
+use tokio::{sync::mpsc::channel, task::spawn_blocking};
+use tower::{buffer::BufferLayer, ServiceBuilder};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{Any, CorsLayer},
+    trace::TraceLayer,
+};
 
-use crate::State;
+use crate::{
+    acyclic, annotated_snapshot, artist_counts, center_community, csv_rows, degree_snapshots,
+    degree_stats, dot_document, graph_etag, graph_version, html_document, limit_clients, metrics,
+    song_communities, song_ranks, spanning_tree, to_adjacency, to_graphml, turtle_document,
+    CacheConnection, ClientRateLimiter, GraphEvent, GraphFormat, GraphOptions, PathDistance,
+    Recorder, RelationshipOrder, RelationshipType, RequestTimings, SampledOnResponse, SearchSort,
+    SongData, State,
+};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-static DEGREE: u8 = 2;
+static MAX_BATCH_IDS: usize = 50;
+static PER_PAGE: usize = 10;
+static STREAM_BUFFER: usize = 64;
+static DAMPING: f64 = 0.85;
+static ITERATIONS: usize = 20;
+static MAX_ITERATIONS: usize = 100;
+static AUTO_DEGREE: &str = "auto";
+
+/// Header carrying the token required by admin routes.
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// Header used to override the Genius API key for a single request.
+pub const GENIUS_KEY_HEADER: &str = "x-genius-key";
+
+/// Read the Genius API key override from the request headers.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+///
+/// # Returns
+///
+/// The overriding Genius API key, if one was provided.
+fn genius_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(GENIUS_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+}
+
+/// The shared application state, or a copy of it scoped to a single request.
+enum RequestState<'a, S> {
+    /// The shared state.
+    Shared(&'a S),
+    /// A copy of the state making Genius calls with the request's own API key.
+    Keyed(S),
+}
+
+impl<S> Deref for RequestState<'_, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        match self {
+            Self::Shared(state) => state,
+            Self::Keyed(state) => state,
+        }
+    }
+}
+
+/// Scope the shared state to the Genius API key override in the request headers, if any.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// The state to serve the request with.
+fn request_state<'a, C: CacheConnection, S: State<C>>(
+    headers: &HeaderMap,
+    state: &'a S,
+) -> RequestState<'a, S> {
+    match genius_key(headers) {
+        Some(key) => RequestState::Keyed(state.with_genius_key(key)),
+        None => RequestState::Shared(state),
+    }
+}
+
+/// Read a boolean flag from the query parameters.
+///
+/// # Args
+///
+/// * `params` - The query parameters.
+/// * `name` - The name of the flag.
+///
+/// # Returns
+///
+/// Whether the flag is set to `true`.
+fn flag(params: &HashMap<String, String>, name: &str) -> bool {
+    params.get(name).map(|v| v == "true").unwrap_or(false)
+}
+
+/// Check whether a request's `If-None-Match` header matches an entity tag.
+/// Tags are compared weakly, ignoring any `W/` prefix, and `*` matches any tag.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `etag` - The entity tag of the response.
+///
+/// # Returns
+///
+/// Whether the client already has the response.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = etag.trim_start_matches("W/");
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == opaque)
+}
+
+/// Read a comma-separated list of Genius IDs from the query parameters.
+/// Entries that are not valid IDs are ignored.
+///
+/// # Args
+///
+/// * `params` - The query parameters.
+/// * `name` - The name of the parameter.
+///
+/// # Returns
+///
+/// The Genius IDs, empty if the parameter is missing.
+fn id_list<T: FromIterator<u32>>(params: &HashMap<String, String>, name: &str) -> T {
+    params
+        .get(name)
+        .map(|ids| ids.split(',').filter_map(|id| id.parse().ok()).collect())
+        .unwrap_or_else(|| std::iter::empty().collect())
+}
+
+/// Read the requested song data fields from the query parameters.
+///
+/// # Args
+///
+/// * `params` - The query parameters.
+///
+/// # Returns
+///
+/// The names of the song data fields to return, or `None` to return every field.
+fn sparse_fields(params: &HashMap<String, String>) -> Option<HashSet<String>> {
+    params.get("fields").map(|fields| {
+        fields
+            .split(',')
+            .map(|field| field.trim().to_string())
+            .collect()
+    })
+}
 
 /// Get the current version of the API.
 ///
@@ -27,10 +197,148 @@ pub async fn version() -> Result<Json<Value>, (StatusCode, String)> {
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
+/// Handler for the health route.
+/// Checks that Redis is reachable with a `PING`.
+///
+/// # Args
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// The service status, including whether maintenance mode is on,
+/// or `503 Service Unavailable` if Redis cannot be reached.
+#[cfg(not(tarpaulin_include))]
+pub async fn health<C: CacheConnection>(
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let unavailable = |e: String| (StatusCode::SERVICE_UNAVAILABLE, e);
+    let mut con = state
+        .connection()
+        .await
+        .map_err(|e| unavailable(e.to_string()))?;
+    con.query_cmd::<String>(&cmd("PING"))
+        .await
+        .map_err(|e| unavailable(e.to_string()))?;
+    Ok(Json(json!({
+        "status": "ok",
+        "maintenance": state.in_maintenance(),
+    })))
+}
+
+/// Check that a request carries the admin token.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// `404 Not Found` if admin routes are disabled, or `403 Forbidden` without the admin token.
+fn check_admin<C: CacheConnection>(
+    headers: &HeaderMap,
+    state: &impl State<C>,
+) -> Result<(), (StatusCode, String)> {
+    let Some(token) = state.admin_token() else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Admin routes are disabled".to_string(),
+        ));
+    };
+    let given = headers
+        .get(ADMIN_TOKEN_HEADER)
+        .map(|v| v.as_bytes())
+        .unwrap_or_default();
+    if !bool::from(given.ct_eq(token.as_bytes())) {
+        return Err((StatusCode::FORBIDDEN, "Invalid admin token".to_string()));
+    }
+    Ok(())
+}
+
+/// Handler for the maintenance mode admin route.
+/// Turns maintenance mode on or off with the `enabled` query parameter.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// Whether maintenance mode is now on, or `403 Forbidden` without the admin token.
+#[cfg(not(tarpaulin_include))]
+pub async fn maintenance<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    check_admin(&headers, state.as_ref())?;
+    if let Some(enabled) = params.get("enabled") {
+        let enabled = enabled.parse::<bool>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "enabled must be true or false".to_string(),
+            )
+        })?;
+        state.maintenance().store(enabled, Ordering::SeqCst);
+    }
+    Ok(Json(json!({ "maintenance": state.in_maintenance() })))
+}
+
+/// Handler for the cache warming admin route.
+/// Takes a JSON array of Genius song IDs, and fetches and caches each song and its relationships.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `state` - The shared application state.
+/// * `ids` - The Genius IDs of the songs from the request body.
+///
+/// # Returns
+///
+/// How many songs were newly cached or already cached, and the songs that failed,
+/// or `403 Forbidden` without the admin token.
+#[cfg(not(tarpaulin_include))]
+pub async fn warm<C: CacheConnection>(
+    headers: HeaderMap,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+    Json(ids): Json<Vec<u32>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    check_admin(&headers, state.as_ref())?;
+    Ok(Json(json!(state.warm(&ids).await)))
+}
+
+/// Handler for the cache invalidation admin route.
+/// Removes a song's cached data and relationships so they are fetched from Genius again.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// The number of cache keys removed, or `403 Forbidden` without the admin token.
+#[cfg(not(tarpaulin_include))]
+pub async fn invalidate<C: CacheConnection>(
+    headers: HeaderMap,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    check_admin(&headers, state.as_ref())?;
+    Ok(Json(json!({ "removed": state.invalidate(song_id).await? })))
+}
+
 /// Handler for the search route.
+/// Results are paged when either `page` or `per_page` is given,
+/// wrapping the songs in an envelope with the page number and total number of results.
 ///
 /// # Args
 ///
+/// * `headers` - The request headers.
 /// * `params` - The query parameters.
 /// * `state` - The shared application state.
 ///
@@ -38,19 +346,182 @@ pub async fn version() -> Result<Json<Value>, (StatusCode, String)> {
 ///
 /// A server response.
 #[cfg(not(tarpaulin_include))]
-pub async fn search<C: ConnectionLike + Send>(
+pub async fn search<C: CacheConnection>(
+    headers: HeaderMap,
     Query(params): Query<HashMap<String, String>>,
     AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     let query = params.get("q").map(|s| s.as_str()).unwrap_or("");
-    Ok(Json(json!(state.search(query).await?)))
+    let state = request_state(&headers, state.as_ref());
+    let sort = params.get("sort").map(SearchSort::from).unwrap_or_default();
+    let page = page_param(&params, "page", 1)?;
+    let per_page = page_param(&params, "per_page", PER_PAGE)?;
+    let mut body = if params.contains_key("page") || params.contains_key("per_page") {
+        json!(state.search_paged(query, sort, page, per_page).await?)
+    } else {
+        json!(state.search_sorted(query, sort).await?)
+    };
+    let songs = match &mut body {
+        Value::Object(page) => page.get_mut("results"),
+        songs => Some(songs),
+    };
+    if let (Some(fields), Some(Value::Array(songs))) = (sparse_fields(&params), songs) {
+        for song in songs {
+            SongData::retain_fields(song, &fields);
+        }
+    }
+    Ok(Json(body))
 }
 
-/// Handler for the graph route.
+/// Parse a pagination query parameter.
 ///
 /// # Args
 ///
 /// * `params` - The query parameters.
+/// * `name` - The name of the parameter.
+/// * `default` - The value used when the parameter is missing.
+///
+/// # Returns
+///
+/// The parameter value, or an error response if it isn't a number.
+fn page_param(
+    params: &HashMap<String, String>,
+    name: &str,
+    default: usize,
+) -> Result<usize, (StatusCode, String)> {
+    params
+        .get(name)
+        .map(|value| value.parse::<usize>())
+        .transpose()
+        .map(|value| value.unwrap_or(default))
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("{} must be a number", name),
+            )
+        })
+}
+
+/// Handler for the artist bridges route.
+/// Takes the two artists as `from` and `to` query parameters,
+/// and the degree of the graph searched as for the graph route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn bridges<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let (Some(from), Some(to)) = (params.get("from"), params.get("to")) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "from and to must name artists".to_string(),
+        ));
+    };
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    Ok(Json(json!(
+        state.artist_bridges(from, to, degree, options).await?
+    )))
+}
+
+/// Handler for the artist search route.
+/// Finds the artist named by the `q` query parameter, so graphs can be built from their songs.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn artist_search<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let Some(name) = params.get("q") else {
+        return Err((StatusCode::BAD_REQUEST, "q must name an artist".to_string()));
+    };
+    let state = request_state(&headers, state.as_ref());
+    match state.search_artist(name).await? {
+        Some(artist) => Ok(Json(json!(artist))),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            format!("No artist found for {}", name),
+        )),
+    }
+}
+
+/// Handler for the artist graph route.
+/// Builds a graph outwards from every song of an artist, given by their Genius ID,
+/// taking the same degree and options as the graph route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `artist_id` - Genius artist ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn artist_graph<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(artist_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Response, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let build = state.graph_for_artist(artist_id, degree, options).await?;
+    let mut graph = json!(build.graph);
+    if let (true, Value::Object(graph)) = (build.truncated, &mut graph) {
+        graph.insert("truncated".into(), json!(true));
+    }
+    let truncated = if build.truncated { "true" } else { "false" };
+    Ok((
+        [
+            (TRUNCATED_HEADER, truncated),
+            (LEGACY_TRUNCATED_HEADER, truncated),
+        ],
+        Json(graph),
+    )
+        .into_response())
+}
+
+/// Handler for the song route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
 /// * `song_id` - Genius song ID from the URL path.
 /// * `state` - The shared application state.
 ///
@@ -58,15 +529,1178 @@ pub async fn search<C: ConnectionLike + Send>(
 ///
 /// A server response.
 #[cfg(not(tarpaulin_include))]
-pub async fn graph<C: ConnectionLike + Send>(
+pub async fn song<C: CacheConnection>(
+    headers: HeaderMap,
     Query(params): Query<HashMap<String, String>>,
     Path(song_id): Path<u32>,
     AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    let degree: u8 = params
+    let state = request_state(&headers, state.as_ref());
+    let mut lookup = json!(state.song_lookup(song_id).await?);
+    if let (Some(fields), Some(song)) = (sparse_fields(&params), lookup.get_mut("data")) {
+        SongData::retain_fields(song, &fields);
+    }
+    Ok(Json(lookup))
+}
+
+/// Handler for the batch songs route.
+/// Takes the songs as a comma-separated `ids` query parameter.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn songs<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let ids: Vec<u32> = id_list(&params, "ids");
+    if ids.is_empty() || ids.len() > MAX_BATCH_IDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("ids must list between 1 and {} songs", MAX_BATCH_IDS),
+        ));
+    }
+    Ok(Json(json!(state.songs(&ids).await?)))
+}
+
+/// Handler for the batch relationships route.
+/// Takes the songs as a comma-separated `ids` query parameter.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn relationships_batch<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let ids: Vec<u32> = id_list(&params, "ids");
+    if ids.is_empty() || ids.len() > MAX_BATCH_IDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("ids must list between 1 and {} songs", MAX_BATCH_IDS),
+        ));
+    }
+    Ok(Json(json!(state.relationships_batch(&ids).await?)))
+}
+
+/// Handler for the full song route, combining song data and relationships.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn song_full<C: CacheConnection>(
+    headers: HeaderMap,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    Ok(Json(json!(state.song_detail_lookup(song_id).await?)))
+}
+
+/// Handler for the relationships route.
+/// Relationships come strongest first, or as Genius listed them if `order=insertion`.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn relationships<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let mut lookup = state.relationships_lookup(song_id).await?;
+    if let Some(relationships) = &mut lookup.data {
+        params
+            .get("order")
+            .map(RelationshipOrder::from)
+            .unwrap_or_default()
+            .apply(relationships);
+    }
+    Ok(Json(json!(lookup)))
+}
+
+/// Handler for the relationship counts route.
+/// Responds with how many relationships of each type a song has, without the related songs.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn relationship_counts<C: CacheConnection>(
+    headers: HeaderMap,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    Ok(Json(json!(state.relationship_counts(song_id).await?)))
+}
+
+/// Read the graph degree and options from the query parameters.
+/// Degrees above the deployment's ceiling are rejected,
+/// and degrees above the deployment's maximum are lowered to the maximum.
+/// Rejects combinations that could only produce a pointless graph.
+///
+/// # Args
+///
+/// * `params` - The query parameters.
+/// * `relevant_types` - The relationship types graphs follow unless the request asks for others.
+/// * `max_degree` - The highest degree of separation this deployment builds to, if any.
+/// * `degree_ceiling` - The highest degree of separation a request may ask for.
+/// * `default_degree` - The degree of separation used when the request doesn't give one.
+///
+/// # Returns
+///
+/// The maximum degree of separation and the graph options,
+/// or a `400 Bad Request` explaining the contradiction.
+fn graph_params(
+    params: &HashMap<String, String>,
+    relevant_types: &HashSet<RelationshipType>,
+    max_degree: Option<u8>,
+    degree_ceiling: u8,
+    default_degree: u8,
+) -> Result<(u8, GraphOptions), (StatusCode, String)> {
+    let degree = params
         .get("degree")
-        .map(|d| d.parse().unwrap_or(DEGREE))
-        .unwrap_or(DEGREE);
-    let graph = state.graph(song_id, degree).await?;
-    Ok(Json(json!(graph)))
+        .map(|degree| degree.parse::<u8>())
+        .transpose()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "degree must be a number of degrees".to_string(),
+            )
+        })?
+        .unwrap_or(default_degree);
+    if degree > degree_ceiling {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("degree must be at most {}", degree_ceiling),
+        ));
+    }
+    let degree = max_degree.map_or(degree, |max_degree| degree.min(max_degree));
+    let only = match params.get("only").map(RelationshipType::from) {
+        Some(RelationshipType::Unknown) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "only must be a relationship type".to_string(),
+            ));
+        }
+        Some(only)
+            if !relevant_types
+                .iter()
+                .any(|relevant| relevant.family() == only.family()) =>
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "only={} excludes every relevant relationship type",
+                    only.as_str()
+                ),
+            ));
+        }
+        only => only,
+    };
+    let types = match params
+        .get("types")
+        .map(|types| RelationshipType::parse_list(types))
+    {
+        Some(Err(e)) => return Err((StatusCode::BAD_REQUEST, e)),
+        Some(Ok(types)) => Some(types.into_iter().collect()),
+        None => None,
+    };
+    let year_span = params
+        .get("year_span")
+        .map(|span| span.parse::<u16>())
+        .transpose()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "year_span must be a number of years".to_string(),
+            )
+        })?;
+    let min_weight = params
+        .get("min_weight")
+        .map(|weight| weight.parse::<u32>())
+        .transpose()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "min_weight must be a relationship weight".to_string(),
+            )
+        })?;
+    let max_distance = params
+        .get("max_distance")
+        .map(|distance| {
+            distance
+                .parse::<f64>()
+                .ok()
+                .and_then(PathDistance::new)
+                .ok_or(())
+        })
+        .transpose()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "max_distance must be a non-negative number".to_string(),
+            )
+        })?;
+    let max_nodes = params
+        .get("max_nodes")
+        .map(|nodes| {
+            nodes
+                .parse::<usize>()
+                .ok()
+                .filter(|&nodes| nodes > 0)
+                .ok_or(())
+        })
+        .transpose()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "max_nodes must be a positive number of nodes".to_string(),
+            )
+        })?;
+    let options = GraphOptions {
+        provenance: flag(params, "provenance"),
+        exclude_center_artist: flag(params, "exclude_center_artist"),
+        only,
+        types,
+        pin: id_list(params, "pin"),
+        breadcrumbs: flag(params, "breadcrumbs"),
+        year_span,
+        min_weight,
+        best_effort: flag(params, "best_effort"),
+        max_distance,
+        max_nodes,
+        include_inverse: flag(params, "bidirectional"),
+        include_albums: flag(params, "include_albums"),
+    };
+    Ok((degree, options))
+}
+
+/// Header naming the Genius ID of the song a graph was built from.
+pub const CENTER_ID_HEADER: &str = "x-center-id";
+
+/// Header saying whether a graph was left partially expanded to stay within limits.
+pub const TRUNCATED_HEADER: &str = "x-graph-truncated";
+
+/// Former name of [`TRUNCATED_HEADER`], still sent for clients that read it.
+pub const LEGACY_TRUNCATED_HEADER: &str = "x-truncated";
+
+/// Header giving the most nodes a graph may contain, if limited.
+pub const NODE_LIMIT_HEADER: &str = "x-graph-node-limit";
+
+/// Header giving a version of a graph that only changes when its data changes.
+pub const VERSION_HEADER: &str = "x-graph-version";
+
+/// Header giving the maximum degree of separation a graph was built to.
+pub const DEGREE_HEADER: &str = "x-graph-degree";
+
+/// Header naming the file a recorded request's Genius responses were saved to.
+pub const RECORDING_HEADER: &str = "x-recording";
+
+/// Header saying whether some songs in a best-effort graph failed to load.
+pub const PARTIAL_HEADER: &str = "x-partial";
+
+/// Header counting relationships left out of a graph by type, e.g. `covered_by=12,remix_of=3`.
+pub const FILTERED_TYPES_HEADER: &str = "x-graph-filtered-types";
+
+/// Handler for the graph route.
+/// Without a `degree`, the graph is built to the deployment's default degree,
+/// and with `degree=auto`, one is chosen to give an interesting but not overwhelming graph.
+/// Responds with JSON, or streams CSV with one row per edge if `format=csv`.
+/// With `format=html`, responds with a standalone page rendering the graph,
+/// with `format=turtle`, responds with the graph as RDF triples,
+/// with `format=dot`, responds with the graph as a Graphviz digraph,
+/// with `format=graphml`, responds with the graph as a GraphML document,
+/// and with `format=adjacency`, responds with JSON lists of songs and of edges
+/// between Genius IDs, rather than between node indices.
+/// Without a recognised `format`, the format the `Accept` header prefers is used,
+/// falling back to the deployment's default format.
+/// With `timing=true`, JSON graphs include a `timings` breakdown of the request.
+/// With `cluster=true`, only the community of songs the center belongs to is returned.
+/// With `tree=true`, only the edges of a maximum spanning tree are returned,
+/// keeping the strongest relationships.
+/// With `dag=true`, cycles are broken by dropping their weakest edges,
+/// and JSON graphs list the dropped edges under `removed_edges`.
+/// With `best_effort=true`, songs whose relationships fail to load are left unexpanded,
+/// and JSON graphs list them under `errors`.
+/// With `max_nodes`, at most that many songs are added, within any limit the deployment sets,
+/// and JSON graphs left partially expanded are marked with `truncated`.
+/// JSON graphs give their `version`, `degree`, and `node_limit`, if limited,
+/// matching the `X-Graph-Version`, `X-Graph-Degree`, and `X-Graph-Node-Limit` headers,
+/// and count the relationships left out by type under `filtered_types`.
+/// With `types`, a comma-separated list such as `samples,remix_of`, graphs follow
+/// those relationship types instead of the deployment's relevant types.
+/// With `bidirectional=true`, each relationship found is also added as its inverse
+/// from the related song back, so songs can reach each other in either direction.
+/// With `include_albums=true`, JSON nodes give the album their song is on, if any,
+/// once the song has been fetched from Genius.
+/// With `record=true`, if the deployment allows it and the request carries the admin token,
+/// the graph is built without the cache and the Genius responses are saved to a file
+/// named in the `X-Recording` header.
+/// With `codes=true`, JSON edges give relationship types by numeric code,
+/// and the graph is wrapped with a `legend` naming each code.
+/// JSON song data can be limited to the fields listed in `fields`.
+/// With `edge_sources=true`, responds with the nodes and edges by Genius ID,
+/// each edge recording how it was discovered.
+/// Responses carry an `ETag` unless `timing=true`, and requests whose `If-None-Match`
+/// matches it get an empty `304 Not Modified` response.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `center` - Genius song ID, or a search query for the center song, from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(mut params): Query<HashMap<String, String>>,
+    Path(center): Path<String>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Response, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let start = Instant::now();
+    let timings = Arc::new(RequestTimings::default());
+    let auto_degree = params.get("degree").map(String::as_str) == Some(AUTO_DEGREE);
+    if auto_degree {
+        params.remove("degree");
+    }
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let best_effort = options.best_effort;
+    let node_limit = options.node_limit(state.max_nodes());
+    let recorder = if flag(&params, "record") && state.recording_dir().is_some() {
+        check_admin(&headers, state.deref())?;
+        Some(Arc::new(Recorder::default()))
+    } else {
+        None
+    };
+    let work = timings.clone().scope(async {
+        let Some(song_id) = state.resolve_center(&center).await? else {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("No song found for {}", center),
+            ));
+        };
+        if auto_degree {
+            let (degree, build) = state.auto_degree_graph(song_id, options).await?;
+            Ok((song_id, degree, build))
+        } else {
+            let build = state.coalesced_graph(song_id, degree, options).await?;
+            Ok((song_id, degree, build))
+        }
+    });
+    let (song_id, degree, build) = match &recorder {
+        Some(recorder) => recorder.clone().scope(work).await?,
+        None => work.await?,
+    };
+    let mut graph_headers = HeaderMap::new();
+    if let (Some(recorder), Some(dir)) = (recorder, state.recording_dir()) {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        let name = format!("graph-{}-{}-{}.json", song_id, degree, millis);
+        let (recording, path) = (recorder.recording(), dir.join(&name));
+        spawn_blocking(move || recording.save(path))
+            .await
+            .unwrap_or_else(|e| Err(IoError::from(e).into()))?;
+        if let Ok(name) = HeaderValue::from_str(&name) {
+            graph_headers.insert(RECORDING_HEADER, name);
+        }
+    }
+    graph_headers.insert(CENTER_ID_HEADER, HeaderValue::from(song_id));
+    graph_headers.insert(DEGREE_HEADER, HeaderValue::from(u16::from(degree)));
+    let truncated = HeaderValue::from_static(if build.truncated { "true" } else { "false" });
+    graph_headers.insert(TRUNCATED_HEADER, truncated.clone());
+    graph_headers.insert(LEGACY_TRUNCATED_HEADER, truncated);
+    if let Some(node_limit) = node_limit {
+        graph_headers.insert(NODE_LIMIT_HEADER, HeaderValue::from(node_limit));
+    }
+    let version = graph_version(&build.graph);
+    let etag = (!flag(&params, "timing")).then(|| graph_etag(&version, &params));
+    if let Ok(version) = HeaderValue::from_str(&version) {
+        graph_headers.insert(VERSION_HEADER, version);
+    }
+    if let Some(Ok(etag)) = etag.as_deref().map(HeaderValue::from_str) {
+        graph_headers.insert(header::ETAG, etag);
+    }
+    let filtered_types = build
+        .filtered_types
+        .iter()
+        .map(|(relationship_type, &count)| (relationship_type.as_str(), count))
+        .collect::<BTreeMap<_, _>>();
+    if !filtered_types.is_empty() {
+        let header = filtered_types
+            .iter()
+            .map(|(relationship_type, count)| format!("{}={}", relationship_type, count))
+            .collect::<Vec<_>>()
+            .join(",");
+        if let Ok(header) = HeaderValue::from_str(&header) {
+            graph_headers.insert(FILTERED_TYPES_HEADER, header);
+        }
+    }
+    if best_effort {
+        graph_headers.insert(
+            PARTIAL_HEADER,
+            HeaderValue::from_static(if build.errors.is_empty() {
+                "false"
+            } else {
+                "true"
+            }),
+        );
+    }
+    if etag
+        .as_deref()
+        .is_some_and(|etag| etag_matches(&headers, etag))
+    {
+        return Ok((StatusCode::NOT_MODIFIED, graph_headers).into_response());
+    }
+    if flag(&params, "edge_sources") {
+        return Ok((graph_headers, Json(json!(annotated_snapshot(&build)))).into_response());
+    }
+    let errors = build.errors;
+    let truncated = build.truncated;
+    let center = build
+        .graph
+        .node_indices()
+        .find(|&node| build.graph[node].song.id == song_id);
+    let graph = match (flag(&params, "cluster"), center) {
+        (true, Some(center)) => center_community(&build.graph, center),
+        _ => build.graph,
+    };
+    let graph = if flag(&params, "tree") {
+        spanning_tree(&graph)
+    } else {
+        graph
+    };
+    let mut removed_edges = None;
+    let graph = if flag(&params, "dag") {
+        let (dag, removed) = acyclic(&graph);
+        removed_edges = Some(removed);
+        dag
+    } else {
+        graph
+    };
+    let format = params
+        .get("format")
+        .and_then(|format| GraphFormat::parse(format).ok())
+        .or_else(|| {
+            headers
+                .get(header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .and_then(GraphFormat::from_accept)
+        })
+        .unwrap_or(state.default_format());
+    if format == GraphFormat::Csv {
+        let rows = stream::iter(csv_rows(graph).map(Ok::<_, Infallible>));
+        return Ok((
+            graph_headers,
+            [(header::CONTENT_TYPE, "text/csv")],
+            StreamBody::new(rows),
+        )
+            .into_response());
+    }
+    if format == GraphFormat::Turtle {
+        return Ok((
+            graph_headers,
+            [(header::CONTENT_TYPE, "text/turtle")],
+            turtle_document(&graph),
+        )
+            .into_response());
+    }
+    if format == GraphFormat::Dot {
+        return Ok((
+            graph_headers,
+            [(header::CONTENT_TYPE, "text/vnd.graphviz")],
+            dot_document(&graph),
+        )
+            .into_response());
+    }
+    if format == GraphFormat::GraphMl {
+        return Ok((
+            graph_headers,
+            [(header::CONTENT_TYPE, "application/xml")],
+            to_graphml(&graph),
+        )
+            .into_response());
+    }
+    if format == GraphFormat::Adjacency {
+        let mut adjacency = json!(to_adjacency(&graph));
+        if let (Some(fields), Some(Value::Array(nodes))) =
+            (sparse_fields(&params), adjacency.get_mut("nodes"))
+        {
+            for song in nodes.iter_mut() {
+                SongData::retain_fields(song, &fields);
+            }
+        }
+        return Ok((graph_headers, Json(adjacency)).into_response());
+    }
+    if format == GraphFormat::Html {
+        return Ok((
+            graph_headers,
+            [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+            html_document(&graph),
+        )
+            .into_response());
+    }
+    if flag(&params, "snapshots") {
+        return Ok((graph_headers, Json(json!(degree_snapshots(&graph, degree)))).into_response());
+    }
+    let serialize_start = Instant::now();
+    let codes = flag(&params, "codes");
+    let mut graph = if codes {
+        json!(graph.map(
+            |_, node| node,
+            |_, edge| {
+                json!({
+                    "relationship_type": edge.relationship_type.code(),
+                    "weight": edge.weight,
+                })
+            },
+        ))
+    } else {
+        json!(graph)
+    };
+    if let (Some(fields), Some(Value::Array(nodes))) =
+        (sparse_fields(&params), graph.get_mut("nodes"))
+    {
+        for song in nodes.iter_mut().filter_map(|node| node.get_mut("song")) {
+            SongData::retain_fields(song, &fields);
+        }
+    }
+    if let (Some(removed_edges), Value::Object(graph)) = (removed_edges, &mut graph) {
+        graph.insert("removed_edges".into(), json!(removed_edges));
+    }
+    if let (false, Value::Object(graph)) = (errors.is_empty(), &mut graph) {
+        graph.insert("errors".into(), json!(errors));
+    }
+    if let (true, Value::Object(graph)) = (truncated, &mut graph) {
+        graph.insert("truncated".into(), json!(true));
+    }
+    if let Value::Object(graph) = &mut graph {
+        graph.insert("version".into(), json!(version));
+        graph.insert("degree".into(), json!(degree));
+        if let Some(node_limit) = node_limit {
+            graph.insert("node_limit".into(), json!(node_limit));
+        }
+        graph.insert("filtered_types".into(), json!(filtered_types));
+    }
+    if let (true, Value::Object(graph)) = (flag(&params, "timing"), &mut graph) {
+        let summary = timings.summary(serialize_start.elapsed(), start.elapsed());
+        graph.insert("timings".into(), json!(summary));
+    }
+    if codes {
+        graph = json!({ "legend": RelationshipType::legend(), "graph": graph });
+    }
+    Ok((graph_headers, Json(graph)).into_response())
+}
+
+/// Handler for the graph metrics route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_metrics<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let graph = state.coalesced_graph(song_id, degree, options).await?.graph;
+    Ok(Json(json!(metrics(&graph))))
+}
+
+/// Handler for the graph stats route.
+/// Counts nodes and edges without returning the graph itself.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_stats<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let graph = state.coalesced_graph(song_id, degree, options).await?.graph;
+    Ok(Json(json!(degree_stats(&graph))))
+}
+
+/// Handler for the graph stream route.
+/// Streams Server-Sent Events for each song and relationship as the graph is built,
+/// named `node` or `edge`, then a `done` event, or an `error` event if the build fails.
+/// Each event's data is the [`GraphEvent`] as JSON.
+/// Songs and relationships are sent before any pruning by `min_weight`.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_stream<C, S>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<S>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, (StatusCode, String)>
+where
+    C: CacheConnection + 'static,
+    S: State<C> + Send + Sync + 'static,
+{
+    // The build outlives the handler, so a scoped state is shared rather than borrowed.
+    let state = match genius_key(&headers) {
+        Some(key) => Arc::new(state.with_genius_key(key)),
+        None => state,
+    };
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let (sender, receiver) = channel(STREAM_BUFFER);
+    tokio::spawn(async move {
+        let last = match state.graph_events(song_id, degree, options, &sender).await {
+            Ok(build) => GraphEvent::Done {
+                truncated: build.truncated,
+            },
+            Err(e) => GraphEvent::Error {
+                message: e.to_string(),
+            },
+        };
+        let _ = sender.send(last).await;
+    });
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|event| (event, receiver))
+    })
+    .map(|event| {
+        Event::default()
+            .event(event.name())
+            .json_data(&event)
+            .map_err(axum::Error::new)
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Handler for the graph reciprocity route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_reciprocity<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let graph = state.coalesced_graph(song_id, degree, options).await?.graph;
+    Ok(Json(json!(state.reciprocity(&graph).await?)))
+}
+
+/// Handler for the graph family tree route.
+/// Responds with the graph re-rooted at its earliest released song,
+/// with degrees measured from that song.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_family_tree<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    Ok(Json(json!(
+        state.family_tree(song_id, degree, options).await?
+    )))
+}
+
+/// Handler for the graph artists route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_artists<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let graph = state.coalesced_graph(song_id, degree, options).await?.graph;
+    Ok(Json(json!(artist_counts(&graph))))
+}
+
+/// Handler for the graph communities route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_communities<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let graph = state.coalesced_graph(song_id, degree, options).await?.graph;
+    Ok(Json(json!(song_communities(&graph))))
+}
+
+/// Handler for the graph PageRank route.
+/// Takes optional `damping` and `iterations` query parameters,
+/// and sorts songs from most to least central if `sort=true`.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_pagerank<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let damping = match params.get("damping").map(|d| d.parse::<f64>()) {
+        None => DAMPING,
+        Some(Ok(damping)) if (0.0..1.0).contains(&damping) => damping,
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "damping must be at least 0 and less than 1".to_string(),
+            ))
+        }
+    };
+    let iterations = match params.get("iterations").map(|i| i.parse::<usize>()) {
+        None => ITERATIONS,
+        Some(Ok(iterations)) if iterations <= MAX_ITERATIONS => iterations,
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("iterations must be at most {}", MAX_ITERATIONS),
+            ))
+        }
+    };
+    let graph = state.coalesced_graph(song_id, degree, options).await?.graph;
+    let mut ranks = song_ranks(&graph, damping, iterations);
+    if flag(&params, "sort") {
+        ranks.sort_by(|a, b| b.score.total_cmp(&a.score));
+    }
+    Ok(Json(json!(ranks)))
+}
+
+/// Handler for the graph shortest cycle route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_shortest_cycle<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, options) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    let graph = state.coalesced_graph(song_id, degree, options).await?.graph;
+    Ok(Json(json!(state.shortest_cycle(&graph).await?)))
+}
+
+/// Handler for the graph delta route.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_delta<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let (degree, _) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    Ok(Json(json!(state.graph_delta(song_id, degree).await?)))
+}
+
+/// Handler for the graph estimate route.
+///
+/// # Args
+///
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_estimate<C: CacheConnection>(
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let (degree, _) = graph_params(
+        &params,
+        state.relevant_types(),
+        state.max_degree(),
+        state.degree_ceiling(),
+        state.default_degree(),
+    )?;
+    Ok(Json(json!(state.estimate(song_id, degree).await?)))
+}
+
+/// Handler for the graph neighbors route.
+/// Neighbors come strongest relationship first, or as Genius listed them if `order=insertion`.
+/// Takes the client's graph as a comma-separated `known` query parameter of at most
+/// [`MAX_BATCH_IDS`] songs.
+///
+/// # Args
+///
+/// * `headers` - The request headers.
+/// * `params` - The query parameters.
+/// * `song_id` - Genius song ID from the URL path.
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// A server response.
+#[cfg(not(tarpaulin_include))]
+pub async fn graph_neighbors<C: CacheConnection>(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Path(song_id): Path<u32>,
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let state = request_state(&headers, state.as_ref());
+    let center_id = params
+        .get("center")
+        .map(|id| id.parse())
+        .transpose()
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "center must be a Genius song ID".to_string(),
+            )
+        })?
+        .unwrap_or(song_id);
+    let known: HashSet<u32> = id_list(&params, "known");
+    if known.len() > MAX_BATCH_IDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("known must list at most {} songs", MAX_BATCH_IDS),
+        ));
+    }
+    let order = params
+        .get("order")
+        .map(RelationshipOrder::from)
+        .unwrap_or_default();
+    Ok(Json(json!(
+        state.neighbors(center_id, song_id, &known, order).await?
+    )))
+}
+
+/// Handler for the unknown relationships debug route.
+///
+/// # Args
+///
+/// * `state` - The shared application state.
+///
+/// # Returns
+///
+/// The distinct raw relationship strings that mapped to `Unknown`.
+#[cfg(not(tarpaulin_include))]
+pub async fn unknown_relationships<C: CacheConnection>(
+    AxumState(state): AxumState<Arc<impl State<C> + Sync>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    Ok(Json(json!(state.unknown_relationships())))
+}
+
+/// Build the application router with all routes and middleware.
+/// Admin routes are only mounted when an admin token is configured.
+///
+/// # Args
+///
+/// * `state` - The shared application state.
+/// * `sampler` - Decides which responses are traced.
+/// * `limiter` - Limits how many requests each client can make.
+///
+/// # Returns
+///
+/// The application router.
+#[cfg(not(tarpaulin_include))]
+pub fn router<C, S>(state: Arc<S>, sampler: SampledOnResponse, limiter: ClientRateLimiter) -> Router
+where
+    C: CacheConnection + 'static,
+    S: State<C> + Send + Sync + 'static,
+{
+    let trace = TraceLayer::new_for_http().on_response(sampler);
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::PUT, Method::POST, Method::DELETE])
+        .allow_headers([
+            HeaderName::from_static(GENIUS_KEY_HEADER),
+            HeaderName::from_static(ADMIN_TOKEN_HEADER),
+            header::IF_NONE_MATCH,
+            header::CONTENT_TYPE,
+        ])
+        .expose_headers([
+            header::ETAG,
+            HeaderName::from_static(CENTER_ID_HEADER),
+            HeaderName::from_static(TRUNCATED_HEADER),
+            HeaderName::from_static(LEGACY_TRUNCATED_HEADER),
+            HeaderName::from_static(PARTIAL_HEADER),
+            HeaderName::from_static(RECORDING_HEADER),
+            HeaderName::from_static(NODE_LIMIT_HEADER),
+            HeaderName::from_static(DEGREE_HEADER),
+            HeaderName::from_static(VERSION_HEADER),
+            HeaderName::from_static(FILTERED_TYPES_HEADER),
+        ])
+        .allow_origin(Any);
+    let route_layers = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(|err: BoxError| async move {
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+        }))
+        .layer(BufferLayer::new(1024))
+        .layer(trace)
+        .layer(cors)
+        .layer(middleware::from_fn_with_state(limiter, limit_clients))
+        .layer(CompressionLayer::new());
+    let mut routes = Router::new()
+        .route("/search", get(search::<C>))
+        .route("/bridges", get(bridges::<C>))
+        .route("/artist", get(artist_search::<C>))
+        .route("/artist/:artist_id/graph", get(artist_graph::<C>))
+        .route("/song/:song_id", get(song::<C>))
+        .route("/song/:song_id/full", get(song_full::<C>))
+        .route("/songs", get(songs::<C>))
+        .route("/relationships", get(relationships_batch::<C>))
+        .route("/relationships/:song_id", get(relationships::<C>))
+        .route(
+            "/relationships/:song_id/count",
+            get(relationship_counts::<C>),
+        )
+        .route("/graph/:song_id", get(graph::<C>))
+        .route("/graph/:song_id/metrics", get(graph_metrics::<C>))
+        .route("/graph/:song_id/stats", get(graph_stats::<C>))
+        .route("/graph/:song_id/stream", get(graph_stream::<C, S>))
+        .route("/graph/:song_id/reciprocity", get(graph_reciprocity::<C>))
+        .route("/graph/:song_id/communities", get(graph_communities::<C>))
+        .route("/graph/:song_id/artists", get(graph_artists::<C>))
+        .route("/graph/:song_id/family-tree", get(graph_family_tree::<C>))
+        .route("/graph/:song_id/delta", get(graph_delta::<C>))
+        .route("/graph/:song_id/pagerank", get(graph_pagerank::<C>))
+        .route(
+            "/graph/:song_id/shortest-cycle",
+            get(graph_shortest_cycle::<C>),
+        )
+        .route("/graph/:song_id/neighbors", get(graph_neighbors::<C>))
+        .route("/graph/:song_id/estimate", get(graph_estimate::<C>))
+        .route("/version", get(version))
+        .route("/health", get(health::<C>))
+        .route(
+            "/debug/unknown-relationships",
+            get(unknown_relationships::<C>),
+        );
+    if state.admin_token().is_some() {
+        routes = routes
+            .route("/admin/maintenance", put(maintenance::<C>))
+            .route("/warm", post(warm::<C>))
+            .route("/cache/song/:song_id", delete(invalidate::<C>));
+    }
+    routes.layer(route_layers).with_state(state)
 }
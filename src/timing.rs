@@ -0,0 +1,136 @@
+//! Per-request timing of cache and Genius work.
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::Timings;
+
+tokio::task_local! {
+    /// Timings of the request being handled by the current task, if it asked for them.
+    static REQUEST_TIMINGS: Arc<RequestTimings>;
+}
+
+/// Kinds of work timed during a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading from or writing to the Redis or on-disk cache.
+    Cache,
+    /// Waiting on the Genius API.
+    Genius,
+}
+
+/// Time spent on each phase of a single request.
+#[derive(Debug, Default)]
+pub struct RequestTimings {
+    /// Microseconds spent on the cache.
+    cache_us: AtomicU64,
+    /// Microseconds spent waiting on Genius.
+    genius_us: AtomicU64,
+}
+
+impl RequestTimings {
+    /// Run a future, recording the time it spends on each phase.
+    /// Work done on other tasks, such as a graph build shared with another request, is not counted.
+    ///
+    /// # Args
+    ///
+    /// * `future` - The request's work.
+    ///
+    /// # Returns
+    ///
+    /// The output of the future.
+    pub async fn scope<F: Future>(self: Arc<Self>, future: F) -> F::Output {
+        REQUEST_TIMINGS.scope(self, future).await
+    }
+
+    /// Summarise the recorded timings.
+    ///
+    /// # Args
+    ///
+    /// * `serialize` - Time spent serializing the response.
+    /// * `total` - Time spent handling the whole request.
+    ///
+    /// # Returns
+    ///
+    /// The timings in milliseconds.
+    pub fn summary(&self, serialize: Duration, total: Duration) -> Timings {
+        let millis = |us: u64| us as f64 / 1000.0;
+        Timings {
+            cache_ms: millis(self.cache_us.load(Ordering::Relaxed)),
+            genius_ms: millis(self.genius_us.load(Ordering::Relaxed)),
+            serialize_ms: serialize.as_secs_f64() * 1000.0,
+            total_ms: total.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Add time spent on a phase to the current request's timings.
+/// Does nothing outside of [`RequestTimings::scope`].
+///
+/// # Args
+///
+/// * `phase` - The kind of work done.
+/// * `elapsed` - How long the work took.
+pub fn record(phase: Phase, elapsed: Duration) {
+    let _ = REQUEST_TIMINGS.try_with(|timings| {
+        let counter = match phase {
+            Phase::Cache => &timings.cache_us,
+            Phase::Genius => &timings.genius_us,
+        };
+        counter.fetch_add(
+            u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    });
+}
+
+/// Await a future, adding the time it takes to the current request's timings.
+///
+/// # Args
+///
+/// * `phase` - The kind of work done.
+/// * `future` - The work.
+///
+/// # Returns
+///
+/// The output of the future.
+pub async fn measure<F: Future>(phase: Phase, future: F) -> F::Output {
+    let start = Instant::now();
+    let output = future.await;
+    record(phase, start.elapsed());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_request_timings() {
+        let timings = Arc::new(RequestTimings::default());
+        timings
+            .clone()
+            .scope(async {
+                record(Phase::Cache, Duration::from_millis(2));
+                record(Phase::Cache, Duration::from_micros(500));
+                measure(Phase::Genius, tokio::time::sleep(Duration::from_millis(5))).await;
+            })
+            .await;
+        record(Phase::Cache, Duration::from_secs(1));
+
+        let result = timings.summary(Duration::from_millis(1), Duration::from_millis(100));
+        assert_eq!(result.cache_ms, 2.5);
+        assert!(result.genius_ms >= 5.0);
+        assert_eq!(result.serialize_ms, 1.0);
+        assert_eq!(result.total_ms, 100.0);
+    }
+}
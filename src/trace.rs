@@ -0,0 +1,123 @@
+//! Sampling for request tracing.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use http::{Response, StatusCode};
+use tower_http::trace::{DefaultOnResponse, OnResponse};
+use tracing::{Level, Span};
+
+/// Decides which responses are worth logging.
+/// Error responses are always logged.
+#[derive(Debug, Clone)]
+pub struct SampledOnResponse {
+    /// Log one in every `rate` responses.
+    rate: u64,
+    /// Always log responses at least this slow.
+    slow_threshold: Option<Duration>,
+    /// Number of responses seen so far.
+    seen: Arc<AtomicU64>,
+}
+
+impl SampledOnResponse {
+    /// Create a new response sampler.
+    ///
+    /// # Args
+    ///
+    /// * `rate` - Log one in every `rate` responses. A rate of zero is treated as one.
+    /// * `slow_threshold` - Always log responses at least this slow.
+    ///
+    /// # Returns
+    ///
+    /// The response sampler.
+    pub fn new(rate: u64, slow_threshold: Option<Duration>) -> Self {
+        Self {
+            rate: rate.max(1),
+            slow_threshold,
+            seen: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Determines if a response should be logged.
+    ///
+    /// # Args
+    ///
+    /// * `status` - The response status code.
+    /// * `latency` - How long the response took.
+    ///
+    /// # Returns
+    ///
+    /// Whether the response should be logged.
+    pub fn should_log(&self, status: StatusCode, latency: Duration) -> bool {
+        let sampled = self
+            .seen
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.rate);
+        let slow = self
+            .slow_threshold
+            .map(|threshold| latency >= threshold)
+            .unwrap_or(false);
+        sampled || slow || status.is_client_error() || status.is_server_error()
+    }
+}
+
+impl<B> OnResponse<B> for SampledOnResponse {
+    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span) {
+        if self.should_log(response.status(), latency) {
+            DefaultOnResponse::new()
+                .level(Level::INFO)
+                .on_response(response, latency, span);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0, 1)]
+    #[case(1, 1)]
+    #[case(10, 10)]
+    fn test_sampled_on_response_new(#[case] rate: u64, #[case] expected: u64) {
+        assert_eq!(SampledOnResponse::new(rate, None).rate, expected);
+    }
+
+    #[rstest]
+    fn test_sampled_on_response_rate() {
+        let sampler = SampledOnResponse::new(3, None);
+        let result = (0..6)
+            .map(|_| sampler.should_log(StatusCode::OK, Duration::ZERO))
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec![true, false, false, true, false, false]);
+    }
+
+    #[rstest]
+    #[case(Duration::from_millis(499), false)]
+    #[case(Duration::from_millis(500), true)]
+    #[case(Duration::from_millis(501), true)]
+    fn test_sampled_on_response_slow(#[case] latency: Duration, #[case] expected: bool) {
+        let sampler = SampledOnResponse::new(u64::MAX, Some(Duration::from_millis(500)));
+        sampler.should_log(StatusCode::OK, Duration::ZERO);
+        assert_eq!(sampler.should_log(StatusCode::OK, latency), expected);
+    }
+
+    #[rstest]
+    #[case(StatusCode::OK, false)]
+    #[case(StatusCode::PERMANENT_REDIRECT, false)]
+    #[case(StatusCode::BAD_REQUEST, true)]
+    #[case(StatusCode::TOO_MANY_REQUESTS, true)]
+    #[case(StatusCode::INTERNAL_SERVER_ERROR, true)]
+    fn test_sampled_on_response_errors(#[case] status: StatusCode, #[case] expected: bool) {
+        let sampler = SampledOnResponse::new(u64::MAX, None);
+        sampler.should_log(StatusCode::OK, Duration::ZERO);
+        assert_eq!(sampler.should_log(status, Duration::ZERO), expected);
+    }
+}
@@ -1,12 +1,1464 @@
-use axum::Json;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::{body::Body, Json};
+use http::{header, Request, StatusCode};
+use petgraph::prelude::DiGraphMap;
+use redis::{cmd, pipe, ErrorKind, RedisError, Value};
+use redis_test::{MockCmd, MockRedisConnection};
 use rstest::*;
-use serde_json::json;
+use serde::Serialize;
+use serde_json::{json, to_vec};
+use tower::ServiceExt;
 
 use sample_graph_api::*;
 
+fn cache_hit<T: Serialize>(key: &str, value: &T) -> MockCmd {
+    MockCmd::new(cmd("GET").arg(key), Ok(Value::Data(to_vec(value).unwrap())))
+}
+
+fn mock_state(mock_commands: Vec<MockCmd>) -> MockState {
+    MockState::new(
+        MockRedisConnection::new(mock_commands),
+        DiGraphMap::new(),
+        HashMap::new(),
+        HashMap::new(),
+        100,
+        true,
+    )
+}
+
 #[rstest]
 async fn test_version() {
     let result = version().await.unwrap();
     assert!(matches!(result, Json(..)));
     assert_eq!(result.0, json!(0));
 }
+
+#[rstest]
+#[case(Some("gzip"), Some("gzip"))]
+#[case(Some("br"), Some("br"))]
+#[case(None, None)]
+#[tokio::test]
+async fn test_router_compression(
+    #[case] accept_encoding: Option<&str>,
+    #[case] expected: Option<&str>,
+) {
+    let songs = (0..20)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let state = mock_state(vec![MockCmd::new(
+        cmd("GET").arg("search/foobar"),
+        Ok(Value::Data(to_vec(&songs).unwrap())),
+    )]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let mut request = Request::builder().uri("/search?q=foobar");
+    if let Some(encoding) = accept_encoding {
+        request = request.header(header::ACCEPT_ENCODING, encoding);
+    }
+    let response = app
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|v| v.to_str().unwrap()),
+        expected
+    );
+}
+
+#[rstest]
+#[case(Some("tenant-key"), Some("tenant-key"))]
+#[case(None, None)]
+#[tokio::test]
+async fn test_router_genius_key(#[case] genius_key: Option<&str>, #[case] expected: Option<&str>) {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let cached = Cached {
+        at: Some(0),
+        data: vec![song.clone()],
+    };
+    let state = Arc::new(MockState::new(
+        MockRedisConnection::new(vec![
+            MockCmd::new(cmd("GET").arg("search/foobar"), Ok(Value::Nil)),
+            MockCmd::with_values(
+                pipe()
+                    .set("search/foobar", to_vec(&cached).unwrap())
+                    .ignore()
+                    .expire("search/foobar", 100)
+                    .ignore(),
+                Ok(vec![Value::Okay, Value::Int(1)]),
+            ),
+        ]),
+        DiGraphMap::new(),
+        HashMap::new(),
+        HashMap::from([("foobar".to_string(), vec![song])]),
+        100,
+        true,
+    ));
+    let app = router(
+        state.clone(),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let mut request = Request::builder().uri("/search?q=foobar");
+    if let Some(key) = genius_key {
+        request = request.header("x-genius-key", key);
+    }
+    let response = app
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(state.genius_keys(), vec![expected.map(String::from)]);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_client_rate_limit() {
+    let app = router(
+        Arc::new(mock_state(vec![])),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(1, Duration::from_secs(60), 1),
+    );
+    let mut statuses = Vec::new();
+    for client in ["10.0.0.1", "10.0.0.1", "10.0.0.2"] {
+        let request = Request::builder()
+            .uri("/version")
+            .header("x-forwarded-for", client)
+            .body(Body::empty())
+            .unwrap();
+        statuses.push(app.clone().oneshot(request).await.unwrap().status());
+    }
+    assert_eq!(
+        statuses,
+        vec![
+            StatusCode::OK,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::OK
+        ]
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_client_rate_limit_cors() {
+    let app = router(
+        Arc::new(mock_state(vec![])),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(1, Duration::from_secs(60), 1),
+    );
+    let mut responses = Vec::new();
+    for method in ["OPTIONS", "GET", "GET"] {
+        let mut request = Request::builder()
+            .method(method)
+            .uri("/version")
+            .header(header::ORIGIN, "https://example.com")
+            .header("x-forwarded-for", "10.0.0.1");
+        if method == "OPTIONS" {
+            request = request.header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET");
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        responses.push((
+            response.status(),
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .map(|v| v.to_str().unwrap().to_string()),
+        ));
+    }
+    assert_eq!(
+        responses,
+        vec![
+            (StatusCode::OK, Some("*".to_string())),
+            (StatusCode::OK, Some("*".to_string())),
+            (StatusCode::TOO_MANY_REQUESTS, Some("*".to_string())),
+        ]
+    );
+}
+
+#[rstest]
+#[case(None, Some("secret"), StatusCode::NOT_FOUND, false)]
+#[case(Some("secret"), None, StatusCode::FORBIDDEN, false)]
+#[case(Some("secret"), Some("wrong"), StatusCode::FORBIDDEN, false)]
+#[case(Some("secret"), Some("secret"), StatusCode::OK, true)]
+#[tokio::test]
+async fn test_router_maintenance(
+    #[case] admin_token: Option<&str>,
+    #[case] request_token: Option<&str>,
+    #[case] expected_status: StatusCode,
+    #[case] expected_maintenance: bool,
+) {
+    let mut state = mock_state(vec![MockCmd::new(cmd("PING"), Ok("PONG"))]);
+    if let Some(token) = admin_token {
+        state = state.with_admin_token(token);
+    }
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let mut request = Request::builder()
+        .method("PUT")
+        .uri("/admin/maintenance?enabled=true");
+    if let Some(token) = request_token {
+        request = request.header("x-admin-token", token);
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), expected_status);
+
+    let request = Request::builder()
+        .uri("/health")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["maintenance"], json!(expected_maintenance));
+}
+
+#[rstest]
+#[case(None, None, StatusCode::NOT_FOUND)]
+#[case(Some("secret"), None, StatusCode::FORBIDDEN)]
+#[case(Some("secret"), Some("wrong"), StatusCode::FORBIDDEN)]
+#[case(Some("secret"), Some("secret"), StatusCode::OK)]
+#[tokio::test]
+async fn test_router_invalidate(
+    #[case] admin_token: Option<&str>,
+    #[case] request_token: Option<&str>,
+    #[case] expected: StatusCode,
+) {
+    let mut state = mock_state(vec![MockCmd::new(
+        cmd("DEL")
+            .arg("song/1")
+            .arg("song_stats/1")
+            .arg("relationships/1")
+            .arg("album/1"),
+        Ok(3),
+    )]);
+    if let Some(token) = admin_token {
+        state = state.with_admin_token(token);
+    }
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let mut request = Request::builder().method("DELETE").uri("/cache/song/1");
+    if let Some(token) = request_token {
+        request = request.header("x-admin-token", token);
+    }
+    let response = app
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), expected);
+    if expected == StatusCode::OK {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({"removed": 3}));
+    }
+}
+
+#[rstest]
+#[case(None, Some("secret"), StatusCode::NOT_FOUND)]
+#[case(Some("secret"), None, StatusCode::FORBIDDEN)]
+#[case(Some("secret"), Some("secret"), StatusCode::OK)]
+#[tokio::test]
+async fn test_router_warm(
+    #[case] admin_token: Option<&str>,
+    #[case] request_token: Option<&str>,
+    #[case] expected: StatusCode,
+) {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let mut state = mock_state(vec![
+        MockCmd::new(
+            cmd("GET").arg("song/1"),
+            Ok(Value::Data(to_vec(&song).unwrap())),
+        ),
+        MockCmd::new(
+            cmd("GET").arg("relationships/1"),
+            Ok(Value::Data(to_vec(&Vec::<Relationship>::new()).unwrap())),
+        ),
+    ]);
+    if let Some(token) = admin_token {
+        state = state.with_admin_token(token);
+    }
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/warm")
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = request_token {
+        request = request.header("x-admin-token", token);
+    }
+    let response = app
+        .oneshot(request.body(Body::from("[1]")).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), expected);
+    if expected == StatusCode::OK {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"cached": 0, "already_cached": 1, "failed": []})
+        );
+    }
+}
+
+#[rstest]
+#[case("PUT", "/admin/maintenance")]
+#[case("POST", "/warm")]
+#[case("DELETE", "/cache/song/1")]
+#[tokio::test]
+async fn test_router_admin_preflight(#[case] method: &str, #[case] uri: &str) {
+    let app = router(
+        Arc::new(mock_state(vec![]).with_admin_token("secret")),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .method("OPTIONS")
+        .uri(uri)
+        .header(header::ORIGIN, "https://example.com")
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-admin-token")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .map(|v| v.to_str().unwrap().to_lowercase())
+            .unwrap_or_default()
+    };
+    assert!(header(header::ACCESS_CONTROL_ALLOW_METHODS).contains(&method.to_lowercase()));
+    assert!(header(header::ACCESS_CONTROL_ALLOW_HEADERS).contains("x-admin-token"));
+}
+
+#[rstest]
+#[case(Ok(Value::Status("PONG".into())), StatusCode::OK)]
+#[case(
+    Err(RedisError::from((ErrorKind::IoError, "Connection refused"))),
+    StatusCode::SERVICE_UNAVAILABLE
+)]
+#[tokio::test]
+async fn test_router_health(
+    #[case] ping: Result<Value, RedisError>,
+    #[case] expected_status: StatusCode,
+) {
+    let state = mock_state(vec![MockCmd::new(cmd("PING"), ping)]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/health")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), expected_status);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    if expected_status == StatusCode::OK {
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], json!("ok"));
+    } else {
+        assert!(String::from_utf8_lossy(&body).contains("Connection refused"));
+    }
+}
+
+#[rstest]
+#[case("/search?q=foobar&fields=id,title", json!([{"id": 1, "title": "Foobar"}]))]
+#[case("/search?q=foobar&fields=id,foobar", json!([{"id": 1}]))]
+#[case("/search?q=foobar", json!([{"id": 1, "title": "Foobar", "artist_name": "The Sillys"}]))]
+#[case("/search?q=foobar&page=1&fields=id", json!({"results": [{"id": 1}], "page": 1, "total": 1}))]
+#[case("/search?q=foobar&page=2&per_page=5", json!({"results": [], "page": 2, "total": 1}))]
+#[case("/song/1?fields=artist_name", json!({"found": true, "data": {"artist_name": "The Sillys"}}))]
+#[tokio::test]
+async fn test_router_sparse_fields(#[case] uri: &str, #[case] expected: serde_json::Value) {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let (key, data) = if uri.starts_with("/search") {
+        ("search/foobar", to_vec(&vec![song]).unwrap())
+    } else {
+        ("song/1", to_vec(&song).unwrap())
+    };
+    let state = mock_state(vec![MockCmd::new(
+        cmd("GET").arg(key),
+        Ok(Value::Data(data)),
+    )]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body, expected);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_csv() {
+    let center = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let relationships = (2..5)
+        .map(|id| {
+            Relationship::new(
+                RelationshipType::Samples,
+                SongData::new(id, "Foo, Bar".into(), "The Seriouses".into()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let state = mock_state(vec![
+        MockCmd::new(
+            cmd("GET").arg("song/1"),
+            Ok(Value::Data(to_vec(&center).unwrap())),
+        ),
+        MockCmd::new(
+            cmd("GET").arg("relationships/1"),
+            Ok(Value::Data(to_vec(&relationships).unwrap())),
+        ),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1?degree=1&format=csv")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert_eq!(body.lines().count(), 4);
+    assert!(body.lines().skip(1).all(|row| row.contains("\"Foo, Bar\"")));
+}
+
+#[rstest]
+#[case("/graph/1?degree=0", None, Some(1), StatusCode::OK, Some("1"))]
+#[case("/graph/foobar?degree=0", Some(("search/foobar", true)), Some(1), StatusCode::OK, Some("1"))]
+#[case("/graph/testing?degree=0", Some(("search/testing", false)), None, StatusCode::NOT_FOUND, None)]
+#[tokio::test]
+async fn test_router_graph_center(
+    #[case] uri: &str,
+    #[case] search: Option<(&str, bool)>,
+    #[case] song: Option<u32>,
+    #[case] expected_status: StatusCode,
+    #[case] expected_center: Option<&str>,
+) {
+    let center = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let mut cmds = Vec::new();
+    if let Some((key, found)) = search {
+        let hits = if found { vec![center.clone()] } else { vec![] };
+        cmds.push(cache_hit(key, &hits));
+    }
+    if let Some(id) = song {
+        cmds.push(cache_hit(&format!("song/{}", id), &center));
+    }
+    let app = router(
+        Arc::new(mock_state(cmds)),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), expected_status);
+    assert_eq!(
+        response
+            .headers()
+            .get("x-center-id")
+            .map(|v| v.to_str().unwrap()),
+        expected_center
+    );
+}
+
+#[rstest]
+#[case(GraphFormat::Json, "/graph/1?degree=0", "application/json")]
+#[case(GraphFormat::Csv, "/graph/1?degree=0", "text/csv")]
+#[case(GraphFormat::Html, "/graph/1?degree=0", "text/html; charset=utf-8")]
+#[case(GraphFormat::Json, "/graph/1?degree=0&format=turtle", "text/turtle")]
+#[case(GraphFormat::Json, "/graph/1?degree=0&format=dot", "text/vnd.graphviz")]
+#[case(
+    GraphFormat::Csv,
+    "/graph/1?degree=0&format=adjacency",
+    "application/json"
+)]
+#[case(
+    GraphFormat::Json,
+    "/graph/1?degree=0&format=graphml",
+    "application/xml"
+)]
+#[case(GraphFormat::Csv, "/graph/1?degree=0&format=json", "application/json")]
+#[case(GraphFormat::Json, "/graph/1?degree=0&format=csv", "text/csv")]
+#[case(GraphFormat::Csv, "/graph/1?degree=0&format=foobar", "text/csv")]
+#[tokio::test]
+async fn test_router_graph_default_format(
+    #[case] default_format: GraphFormat,
+    #[case] uri: &str,
+    #[case] expected: &str,
+) {
+    let center = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let state = mock_state(vec![MockCmd::new(
+        cmd("GET").arg("song/1"),
+        Ok(Value::Data(to_vec(&center).unwrap())),
+    )])
+    .with_default_format(default_format);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], expected);
+}
+
+#[rstest]
+#[case("/graph/1?degree=0", "text/csv", "text/csv")]
+#[case(
+    "/graph/1?degree=0",
+    "text/turtle;q=0.5, text/vnd.graphviz",
+    "text/vnd.graphviz"
+)]
+#[case("/graph/1?degree=0", "image/png", "text/html; charset=utf-8")]
+#[case("/graph/1?degree=0&format=json", "text/csv", "application/json")]
+#[tokio::test]
+async fn test_router_graph_accept(#[case] uri: &str, #[case] accept: &str, #[case] expected: &str) {
+    let center = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let state =
+        mock_state(vec![cache_hit("song/1", &center)]).with_default_format(GraphFormat::Html);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri(uri)
+        .header(header::ACCEPT, accept)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], expected);
+}
+
+#[rstest]
+#[case("/graph/1?degree=0&timing=true", true)]
+#[case("/graph/1?degree=0", false)]
+#[tokio::test]
+async fn test_router_graph_timing(#[case] uri: &str, #[case] expected: bool) {
+    let center = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let state = mock_state(vec![MockCmd::new(
+        cmd("GET").arg("song/1"),
+        Ok(Value::Data(to_vec(&center).unwrap())),
+    )]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body.get("timings").is_some(), expected);
+    if expected {
+        let timings: Timings = serde_json::from_value(body["timings"].clone()).unwrap();
+        assert!(timings.total_ms > 0.0);
+        assert_eq!(timings.genius_ms, 0.0);
+        assert!(timings.cache_ms + timings.genius_ms + timings.serialize_ms <= timings.total_ms);
+    }
+}
+
+#[rstest]
+#[case("/graph/1?degree=auto", "1")]
+#[case("/graph/1?degree=0", "0")]
+#[tokio::test]
+async fn test_router_graph_auto_degree(#[case] uri: &str, #[case] expected: &str) {
+    let songs = (1..3)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![Relationship::new(
+        RelationshipType::Samples,
+        songs[1].clone(),
+    )];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ])
+    .with_interesting_nodes(2..=10);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-graph-degree"], expected);
+}
+
+#[rstest]
+#[case(None, StatusCode::OK)]
+#[case(Some("\"other\""), StatusCode::OK)]
+#[case(Some("*"), StatusCode::NOT_MODIFIED)]
+#[case(Some("ETAG"), StatusCode::NOT_MODIFIED)]
+#[case(Some("\"other\", ETAG"), StatusCode::NOT_MODIFIED)]
+#[tokio::test]
+async fn test_router_graph_etag(#[case] if_none_match: Option<&str>, #[case] expected: StatusCode) {
+    let songs = (1..3)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![Relationship::new(
+        RelationshipType::Samples,
+        songs[1].clone(),
+    )];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1?degree=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(etag.starts_with("W/\""));
+
+    let mut request = Request::builder().uri("/graph/1?degree=1");
+    if let Some(if_none_match) = if_none_match {
+        request = request.header(header::IF_NONE_MATCH, if_none_match.replace("ETAG", &etag));
+    }
+    let response = app
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), expected);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body.is_empty(), expected == StatusCode::NOT_MODIFIED);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_etag_timing() {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let state = mock_state(vec![
+        cache_hit("song/1", &song),
+        cache_hit("relationships/1", &json!([])),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1?degree=1&timing=true")
+        .header(header::IF_NONE_MATCH, "*")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::ETAG).is_none());
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_include_albums() {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let album = AlbumData {
+        id: 54321,
+        name: "Foobar Deluxe".into(),
+        artist: ArtistData {
+            id: 7,
+            name: "The Sillys".into(),
+        },
+    };
+    let state = mock_state(vec![
+        cache_hit("song/1", &song),
+        cache_hit("relationships/1", &json!([])),
+        cache_hit("album/1", &album),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1?degree=1&include_albums=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["nodes"][0]["album"], json!(album));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_stream() {
+    let songs = (1..3)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![Relationship::new(
+        RelationshipType::Samples,
+        songs[1].clone(),
+    )];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1/stream?degree=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/event-stream"
+    );
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let events = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("event:"))
+        .collect::<Vec<_>>();
+    assert_eq!(events, vec!["node", "node", "edge", "done"]);
+    let data = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| serde_json::from_str(data).unwrap())
+        .collect::<Vec<GraphEvent>>();
+    assert_eq!(
+        data[2],
+        GraphEvent::Edge {
+            from: 1,
+            to: 2,
+            relationship: RelationshipType::Samples,
+        }
+    );
+    assert_eq!(data[3], GraphEvent::Done { truncated: false });
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_adjacency() {
+    let songs = (1..4)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![
+        Relationship::new(RelationshipType::Samples, songs[1].clone()),
+        Relationship::new(RelationshipType::Interpolates, songs[2].clone()),
+    ];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let get_json = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+    let graph = get_json("/graph/1?degree=1").await;
+    let adjacency = get_json("/graph/1?degree=1&format=adjacency").await;
+
+    let node_ids = graph["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|node| node["song"]["id"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    let adjacency_ids = adjacency["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|song| song["id"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(adjacency_ids, node_ids);
+
+    let edges = graph["edges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|edge| {
+            json!({
+                "source": node_ids[edge[0].as_u64().unwrap() as usize],
+                "target": node_ids[edge[1].as_u64().unwrap() as usize],
+                "type": edge[2]["relationship_type"],
+            })
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(adjacency["edges"], json!(edges));
+    assert_eq!(
+        adjacency["edges"][1],
+        json!({"source": 1, "target": 3, "type": "interpolates"})
+    );
+}
+
+#[rstest]
+#[case(None, "/graph/1?degree=1", None, "false")]
+#[case(Some(3), "/graph/1?degree=1", Some("3"), "false")]
+#[case(Some(2), "/graph/1?degree=1", Some("2"), "true")]
+#[case(None, "/graph/1?degree=1&max_nodes=2", Some("2"), "true")]
+#[case(Some(2), "/graph/1?degree=1&max_nodes=3", Some("2"), "true")]
+#[tokio::test]
+async fn test_router_graph_limit_headers(
+    #[case] max_nodes: Option<usize>,
+    #[case] uri: &str,
+    #[case] expected_limit: Option<&str>,
+    #[case] expected_truncated: &str,
+) {
+    let songs = (1..4)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = songs[1..]
+        .iter()
+        .map(|song| Relationship::new(RelationshipType::Samples, song.clone()))
+        .collect::<Vec<_>>();
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ])
+    .with_max_nodes(max_nodes);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .map(|v| v.to_str().unwrap().to_string())
+    };
+    assert_eq!(header("x-graph-node-limit").as_deref(), expected_limit);
+    assert_eq!(header("x-graph-degree").as_deref(), Some("1"));
+    assert_eq!(
+        header("x-graph-truncated").as_deref(),
+        Some(expected_truncated)
+    );
+    assert_eq!(header("x-truncated").as_deref(), Some(expected_truncated));
+    let version = header("x-graph-version").unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body.get("truncated").is_some(),
+        expected_truncated == "true"
+    );
+    assert_eq!(body["version"], json!(version));
+    assert_eq!(body["degree"], json!(1));
+    assert_eq!(
+        body.get("node_limit").map(|limit| limit.to_string()),
+        expected_limit.map(String::from)
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_tree() {
+    let songs = (1..4)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![
+        Relationship::new(RelationshipType::Samples, songs[1].clone()),
+        Relationship::new(RelationshipType::Interpolates, songs[2].clone()),
+    ];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1?degree=1&tree=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["nodes"].as_array().unwrap().len(), 3);
+    assert_eq!(body["edges"].as_array().unwrap().len(), 2);
+}
+
+#[rstest]
+#[case("/graph/1?degree=1", 2)]
+#[case("/graph/1?degree=1&types=samples,remix_of", 3)]
+#[case("/graph/1?degree=1&types=remix_of", 2)]
+#[tokio::test]
+async fn test_router_graph_types(#[case] uri: &str, #[case] expected_nodes: usize) {
+    let songs = (1..4)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![
+        Relationship::new(RelationshipType::Samples, songs[1].clone()),
+        Relationship::new(RelationshipType::RemixOf, songs[2].clone()),
+    ];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["nodes"].as_array().unwrap().len(), expected_nodes);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_codes() {
+    let songs = (1..4)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![
+        Relationship::new(RelationshipType::Samples, songs[1].clone()),
+        Relationship::new(RelationshipType::InterpolatedBy, songs[2].clone()),
+    ];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1?degree=1&codes=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let result = body["graph"]["edges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|edge| {
+            let code = edge[2]["relationship_type"].as_u64().unwrap().to_string();
+            RelationshipType::from(body["legend"][code].as_str().unwrap())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        result,
+        vec![RelationshipType::Samples, RelationshipType::InterpolatedBy]
+    );
+}
+
+#[rstest]
+#[case(false, None, false, None)]
+#[case(true, Some("secret"), false, Some(2))]
+#[case(true, Some("wrong"), true, None)]
+#[case(true, None, true, None)]
+#[tokio::test]
+async fn test_router_graph_record(
+    #[case] record: bool,
+    #[case] token: Option<&str>,
+    #[case] expected_forbidden: bool,
+    #[case] expected: Option<usize>,
+) {
+    let songs = (1..3)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let dir = tempfile::tempdir().unwrap();
+    let state = MockState::new(
+        MockRedisConnection::new(vec![]),
+        DiGraphMap::from_edges([
+            (1, 2, RelationshipType::Samples),
+            (2, 1, RelationshipType::SampledIn),
+        ]),
+        songs.iter().map(|song| (song.id, song.clone())).collect(),
+        HashMap::new(),
+        100,
+        true,
+    )
+    .with_recording_dir(dir.path().to_path_buf())
+    .with_admin_token("secret");
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let mut request = Request::builder().uri(format!("/graph/1?degree=1&record={}", record));
+    if let Some(token) = token {
+        request = request.header("x-admin-token", token);
+    }
+    let response = app
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        response.status() == StatusCode::FORBIDDEN,
+        expected_forbidden
+    );
+    let result = response.headers().get("x-recording").map(|name| {
+        let recording = Recording::load(dir.path().join(name.to_str().unwrap())).unwrap();
+        recording.songs.len() + recording.relationships.len()
+    });
+    assert_eq!(result, expected);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_best_effort() {
+    let songs = (1..4)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![
+        Relationship::new(RelationshipType::Samples, songs[1].clone()),
+        Relationship::new(RelationshipType::Samples, songs[2].clone()),
+    ];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+        MockCmd::new::<_, Value>(
+            cmd("GET").arg("relationships/2"),
+            Err(RedisError::from((ErrorKind::ResponseError, "Oops"))),
+        ),
+        cache_hit("relationships/3", &json!([])),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1?degree=2&best_effort=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-partial"], "true");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["nodes"].as_array().unwrap().len(), 3);
+    assert_eq!(body["errors"].as_array().unwrap().len(), 1);
+    assert_eq!(body["errors"][0]["song_id"], 2);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_graph_filtered_types() {
+    let songs = (1..4)
+        .map(|id| SongData::new(id, "Foobar".into(), "The Sillys".into()))
+        .collect::<Vec<_>>();
+    let relationships = vec![
+        Relationship::new(RelationshipType::Samples, songs[1].clone()),
+        Relationship::new(RelationshipType::CoveredBy, songs[2].clone()),
+        Relationship::new(RelationshipType::CoveredBy, songs[1].clone()),
+        Relationship::new(RelationshipType::RemixOf, songs[2].clone()),
+    ];
+    let state = mock_state(vec![
+        cache_hit("song/1", &songs[0]),
+        cache_hit("relationships/1", &relationships),
+    ]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1?degree=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["x-graph-filtered-types"],
+        "covered_by=2,remix_of=1"
+    );
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body["filtered_types"],
+        json!({"covered_by": 2, "remix_of": 1})
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_songs() {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let state = mock_state(vec![MockCmd::new(
+        cmd("GET").arg("song/1"),
+        Ok(Value::Data(to_vec(&song).unwrap())),
+    )]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/songs?ids=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body, json!({"1": song}));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_songs_too_many() {
+    let ids = (1..=51).map(|id| id.to_string()).collect::<Vec<_>>();
+    let app = router(
+        Arc::new(mock_state(vec![])),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri(format!("/songs?ids={}", ids.join(",")))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[rstest]
+#[case("/relationships")]
+#[case("/relationships?ids=")]
+#[case("/relationships?ids=foobar")]
+#[case("/songs")]
+#[case("/songs?ids=")]
+#[case("/songs?ids=foobar")]
+#[tokio::test]
+async fn test_router_relationships_batch_invalid(#[case] uri: &str) {
+    let app = router(
+        Arc::new(mock_state(vec![])),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[rstest]
+#[case("/graph/1/neighbors?center=foobar")]
+#[case("/graph/1/neighbors?center=-1")]
+#[case(&format!(
+    "/graph/1/neighbors?known={}",
+    (1..=51).map(|id| id.to_string()).collect::<Vec<_>>().join(",")
+))]
+#[tokio::test]
+async fn test_router_graph_neighbors_invalid(#[case] uri: &str) {
+    let app = router(
+        Arc::new(mock_state(vec![])),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[rstest]
+#[case(3, None, "/graph/1?degree=3", StatusCode::OK, "3")]
+#[case(
+    3,
+    None,
+    "/graph/1?degree=4",
+    StatusCode::BAD_REQUEST,
+    "degree must be at most 3"
+)]
+#[case(4, Some(2), "/graph/1?degree=4", StatusCode::OK, "2")]
+#[case(
+    4,
+    Some(2),
+    "/graph/1?degree=5",
+    StatusCode::BAD_REQUEST,
+    "degree must be at most 4"
+)]
+#[tokio::test]
+async fn test_router_graph_degree_ceiling(
+    #[case] degree_ceiling: u8,
+    #[case] max_degree: Option<u8>,
+    #[case] uri: &str,
+    #[case] expected_status: StatusCode,
+    #[case] expected: &str,
+) {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let mut state = mock_state(vec![
+        cache_hit("song/1", &song),
+        cache_hit("relationships/1", &Vec::<Relationship>::new()),
+    ])
+    .with_degree_ceiling(degree_ceiling);
+    if let Some(max_degree) = max_degree {
+        state = state.with_max_degree(max_degree);
+    }
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), expected_status);
+    if expected_status == StatusCode::OK {
+        assert_eq!(response.headers()["x-graph-degree"], expected);
+    } else {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, expected.as_bytes());
+    }
+}
+
+#[rstest]
+#[case(Some(2), "/graph/1?degree=1", "1")]
+#[case(Some(1), "/graph/1?degree=1", "1")]
+#[case(Some(1), "/graph/1?degree=5", "1")]
+#[case(Some(1), "/graph/1?degree=6", "1")]
+#[case(Some(0), "/graph/1?degree=2", "0")]
+#[case(None, "/graph/1?degree=6", "6")]
+#[tokio::test]
+async fn test_router_graph_max_degree(
+    #[case] max_degree: Option<u8>,
+    #[case] uri: &str,
+    #[case] expected_degree: &str,
+) {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let mut state = mock_state(vec![
+        cache_hit("song/1", &song),
+        cache_hit("relationships/1", &Vec::<Relationship>::new()),
+    ]);
+    if let Some(max_degree) = max_degree {
+        state = state.with_max_degree(max_degree);
+    }
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-graph-degree"], expected_degree);
+}
+
+#[rstest]
+#[case(None, "2")]
+#[case(Some(3), "3")]
+#[tokio::test]
+async fn test_router_graph_default_degree_header(
+    #[case] default_degree: Option<u8>,
+    #[case] expected: &str,
+) {
+    let song = SongData::new(1, "Foobar".into(), "The Sillys".into());
+    let mut state = mock_state(vec![
+        cache_hit("song/1", &song),
+        cache_hit("relationships/1", &Vec::<Relationship>::new()),
+    ]);
+    if let Some(degree) = default_degree {
+        state = state.with_default_degree(degree);
+    }
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-graph-degree"], expected);
+}
+
+#[rstest]
+#[case(None, 1)]
+#[case(Some(1), 0)]
+#[tokio::test]
+async fn test_router_graph_default_degree(
+    #[case] default_degree: Option<u8>,
+    #[case] expected_uncached: usize,
+) {
+    let relationships = vec![Relationship::new(
+        RelationshipType::Samples,
+        SongData::new(2, "Barfoo".into(), "The Seriouses".into()),
+    )];
+    let mut state = mock_state(vec![
+        MockCmd::new(
+            cmd("GET").arg("relationships/1"),
+            Ok(Value::Data(to_vec(&relationships).unwrap())),
+        ),
+        MockCmd::new(cmd("GET").arg("relationships/2"), Ok(Value::Nil)),
+    ]);
+    if let Some(degree) = default_degree {
+        state = state.with_default_degree(degree);
+    }
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/graph/1/estimate")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["node_count"], json!(2));
+    assert_eq!(body["uncached_count"], json!(expected_uncached));
+}
+
+#[rstest]
+#[case("/graph/1?degree=abc", "degree must be a number of degrees")]
+#[case("/graph/1?degree=-1", "degree must be a number of degrees")]
+#[case("/graph/1?degree=256", "degree must be a number of degrees")]
+#[case("/graph/1?degree=7", "degree must be at most 6")]
+#[case("/graph/1/metrics?degree=255", "degree must be at most 6")]
+#[case("/graph/1?only=", "only must be a relationship type")]
+#[case("/graph/1?only=foobar", "only must be a relationship type")]
+#[case(
+    "/graph/1?only=covered_by",
+    "only=covered_by excludes every relevant relationship type"
+)]
+#[case("/graph/1?types=samples,foobar", "Unknown relationship type: foobar")]
+#[case("/graph/1?year_span=-1", "year_span must be a number of years")]
+#[case(
+    "/graph/1?min_weight=strong",
+    "min_weight must be a relationship weight"
+)]
+#[case(
+    "/graph/1?max_distance=-1",
+    "max_distance must be a non-negative number"
+)]
+#[case(
+    "/graph/1?max_distance=far",
+    "max_distance must be a non-negative number"
+)]
+#[case("/graph/1?max_nodes=0", "max_nodes must be a positive number of nodes")]
+#[case(
+    "/graph/1?max_nodes=many",
+    "max_nodes must be a positive number of nodes"
+)]
+#[case("/bridges?from=The%20Sillys", "from and to must name artists")]
+#[case("/artist", "q must name an artist")]
+#[case("/search?q=foobar&page=first", "page must be a number")]
+#[case("/search?q=foobar&per_page=-1", "per_page must be a number")]
+#[case(
+    "/bridges?from=a&to=b&degree=two",
+    "degree must be a number of degrees"
+)]
+#[case("/graph/1/metrics?degree=abc", "degree must be a number of degrees")]
+#[case("/graph/1/stats?degree=abc", "degree must be a number of degrees")]
+#[case(
+    "/graph/1/pagerank?damping=1",
+    "damping must be at least 0 and less than 1"
+)]
+#[case(
+    "/graph/1/pagerank?damping=foobar",
+    "damping must be at least 0 and less than 1"
+)]
+#[case("/graph/1/pagerank?iterations=101", "iterations must be at most 100")]
+#[case("/graph/1/estimate?degree=abc", "degree must be a number of degrees")]
+#[tokio::test]
+async fn test_router_graph_invalid_params(#[case] uri: &str, #[case] expected: &str) {
+    let app = router(
+        Arc::new(mock_state(vec![])),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body, expected.as_bytes());
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_router_artist_graph_unknown() {
+    let state = mock_state(vec![MockCmd::new(
+        cmd("GET").arg("artist/7"),
+        Ok(Value::Nil),
+    )]);
+    let app = router(
+        Arc::new(state),
+        SampledOnResponse::new(1, None),
+        ClientRateLimiter::new(20, Duration::from_secs(60), 0),
+    );
+    let request = Request::builder()
+        .uri("/artist/7/graph?degree=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}